@@ -0,0 +1,63 @@
+#!/usr/bin/env perl
+# Regenerates src/unicode_tables.rs from the Unicode data shipped with perl:
+#   perl scripts/gen_unicode_tables.pl > src/unicode_tables.rs
+use strict;
+use warnings;
+use Unicode::UCD qw(prop_invlist prop_values prop_value_aliases);
+
+# inversion list to inclusive ranges, with the surrogates cut out since they aren't chars
+sub ranges {
+  my @list = prop_invlist($_[0]);
+  push @list, 0x110000 if @list % 2;
+  my @res;
+  while (my ($lo, $hi) = splice @list, 0, 2) {
+    $hi -= 1;
+    if ($lo < 0xD800 && $hi >= 0xD800) {
+      push @res, [$lo, 0xD7FF];
+      $lo = 0xE000;
+    }
+    $lo = 0xE000 if $lo >= 0xD800 && $lo <= 0xDFFF;
+    push @res, [$lo, $hi] if $lo <= $hi;
+  }
+  return @res;
+}
+
+sub print_ranges {
+  my ($indent, @ranges) = @_;
+  my @items = map { sprintf("('\\u{%X}', '\\u{%X}')", @$_) } @ranges;
+  while (my @line = splice @items, 0, 4) {
+    print $indent, join(", ", @line), ",\n";
+  }
+}
+
+sub print_named_table {
+  my ($name, $prop, @values) = @_;
+  print "pub const $name: &[PropertyValue] = &[\n";
+  for my $value (@values) {
+    my ($short, $long) = prop_value_aliases($prop, $value);
+    print "  (\"$short\", \"$long\", &[\n";
+    print_ranges("    ", ranges("$prop=$short"));
+    print "  ]),\n";
+  }
+  print "];\n\n";
+}
+
+my $version = Unicode::UCD::UnicodeVersion();
+print "// Generated by scripts/gen_unicode_tables.pl from the Unicode $version data shipped\n";
+print "// with perl, don't edit by hand.\n\n";
+printf "pub const UNICODE_VERSION: (u8, u8, u8) = (%s);\n\n", join(", ", split(/\./, $version));
+print "// (short name, long name, ranges) of one value of a property\n";
+print "pub type PropertyValue = (&'static str, &'static str, &'static [(char, char)]);\n\n";
+
+print "// the general categories without subcategories\n";
+print_named_table("GENERAL_CATEGORY", "gc", sort grep { length == 2 && $_ ne "LC" } prop_values("gc"));
+
+print "// the scripts\n";
+print_named_table("SCRIPT", "sc", sort map { (prop_value_aliases("sc", $_))[0] } prop_values("sc"));
+
+for my $prop ("XID_Start", "XID_Continue") {
+  print "pub const ", uc $prop, ": &[(char, char)] = &[\n";
+  print_ranges("  ", ranges($prop));
+  print "];\n";
+  print "\n" if $prop eq "XID_Start";
+}
@@ -0,0 +1,279 @@
+use super::automaton::Automaton;
+use std::collections::{HashMap, VecDeque};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MatchKind {
+  LeftmostFirst, // among matches with the leftmost start, the earliest pattern wins
+  LeftmostLongest, // among matches with the leftmost start, the longest one wins
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Match {
+  pub pattern: usize,
+  pub start: usize, // byte offsets into the haystack
+  pub end: usize,
+}
+
+// trie of the patterns with failure links. As an `Automaton` it walks the trie anchored
+// and accepts exactly the patterns, the same language as their alternation.
+pub struct AhoCorasick {
+  patterns_len: Vec<usize>,
+  goto: Vec<HashMap<char, usize>>,
+  fail: Vec<usize>,
+  own_pattern: Vec<Option<usize>>, // the first pattern spelled by the path to the node
+  outputs: Vec<Vec<usize>>, // every pattern that is a suffix of the path to the node
+  full_dfa: Option<Vec<HashMap<char, usize>>>,
+  max_pattern_len: usize,
+}
+
+impl AhoCorasick {
+  pub fn new(patterns: &[&str]) -> Self {
+    let mut goto: Vec<HashMap<char, usize>> = vec![HashMap::new()];
+    let mut own_pattern = vec![None];
+    for (idx, pattern) in patterns.iter().enumerate() {
+      assert!(!pattern.is_empty(), "pattern {} is empty", idx);
+      let mut curr = 0;
+      for chr in pattern.chars() {
+        curr = match goto[curr].get(&chr) {
+          Some(&next) => next,
+          None => {
+            goto.push(HashMap::new());
+            own_pattern.push(None);
+            let next = goto.len() - 1;
+            goto[curr].insert(chr, next);
+            next
+          },
+        };
+      }
+      if own_pattern[curr].is_none() {
+        own_pattern[curr] = Some(idx);
+      }
+    }
+
+    // breadth first so the failure target of a node is always done before the node
+    let mut fail = vec![0; goto.len()];
+    let mut outputs: Vec<Vec<usize>> = own_pattern.iter().map(|p| p.iter().copied().collect()).collect();
+    let mut queue: VecDeque<usize> = goto[0].values().copied().collect();
+    while let Some(curr) = queue.pop_front() {
+      for (&chr, &next) in &goto[curr] {
+        let mut f = fail[curr];
+        while f != 0 && !goto[f].contains_key(&chr) {
+          f = fail[f];
+        }
+        fail[next] = match goto[f].get(&chr) {
+          Some(&t) if t != next => t,
+          _ => 0,
+        };
+        let inherited = outputs[fail[next]].clone();
+        outputs[next].extend(inherited);
+        queue.push_back(next);
+      }
+    }
+
+    AhoCorasick {
+      patterns_len: patterns.iter().map(|p| p.len()).collect(),
+      goto,
+      fail,
+      own_pattern,
+      outputs,
+      full_dfa: None,
+      max_pattern_len: patterns.iter().map(|p| p.len()).max().unwrap_or(0),
+    }
+  }
+
+  // precompute the search transition of every node on every char of the patterns,
+  // so searching never follows failure links
+  pub fn with_full_dfa(mut self) -> Self {
+    let mut alphabet: Vec<char> = self.goto.iter().flat_map(|g| g.keys().copied()).collect();
+    alphabet.sort_unstable();
+    alphabet.dedup();
+    let mut table: Vec<HashMap<char, usize>> = vec![HashMap::new(); self.goto.len()];
+    // nodes in breadth first order, so table[fail[s]] is complete before s
+    let mut order = vec![0];
+    let mut idx = 0;
+    while idx < order.len() {
+      order.extend(self.goto[order[idx]].values().copied());
+      idx += 1;
+    }
+    for &s in &order {
+      for &chr in &alphabet {
+        let next = match self.goto[s].get(&chr) {
+          Some(&next) => next,
+          None if s == 0 => 0,
+          None => table[self.fail[s]].get(&chr).copied().unwrap_or(0),
+        };
+        if next != 0 {
+          table[s].insert(chr, next);
+        }
+      }
+    }
+    self.full_dfa = Some(table);
+    self
+  }
+
+  pub fn states_size(&self) -> usize {
+    self.goto.len()
+  }
+
+  fn search_transition(&self, mut s: usize, chr: char) -> usize {
+    if let Some(table) = &self.full_dfa {
+      return table[s].get(&chr).copied().unwrap_or(0);
+    }
+    loop {
+      if let Some(&next) = self.goto[s].get(&chr) {
+        return next;
+      }
+      if s == 0 {
+        return 0;
+      }
+      s = self.fail[s];
+    }
+  }
+
+  fn match_ending_at(&self, pattern: usize, end: usize) -> Match {
+    Match { pattern, start: end - self.patterns_len[pattern], end }
+  }
+
+  // calls `step` with the patterns ending at every char boundary after `from`,
+  // stops early when `step` returns false
+  fn scan(&self, haystack: &str, from: usize, mut step: impl FnMut(&[usize], usize) -> bool) {
+    let mut s = 0;
+    for (idx, chr) in haystack[from..].char_indices() {
+      s = self.search_transition(s, chr);
+      if !step(&self.outputs[s], from + idx + chr.len_utf8()) {
+        return;
+      }
+    }
+  }
+
+  pub fn find_overlapping(&self, haystack: &str) -> Vec<Match> {
+    let mut res = vec![];
+    self.scan(haystack, 0, |patterns, end| {
+      res.extend(patterns.iter().map(|&pattern| self.match_ending_at(pattern, end)));
+      true
+    });
+    res
+  }
+
+  pub fn find_all(&self, haystack: &str, kind: MatchKind) -> Vec<Match> {
+    let mut res = vec![];
+    let mut from = 0;
+    loop {
+      let mut best: Option<Match> = None;
+      self.scan(haystack, from, |patterns, end| {
+        for &pattern in patterns {
+          let m = self.match_ending_at(pattern, end);
+          let better = match best {
+            None => true,
+            Some(b) if m.start != b.start => m.start < b.start,
+            Some(b) => match kind {
+              MatchKind::LeftmostFirst => m.pattern < b.pattern,
+              MatchKind::LeftmostLongest => m.end > b.end,
+            },
+          };
+          if better {
+            best = Some(m);
+          }
+        }
+        // a match starting before best.start would have ended by now
+        match best {
+          Some(b) => end < b.start + self.max_pattern_len,
+          None => true,
+        }
+      });
+      match best {
+        Some(b) => {
+          res.push(b);
+          from = b.end;
+        },
+        None => break,
+      }
+    }
+    res
+  }
+}
+
+impl Automaton for AhoCorasick {
+  type State = Option<usize>;
+
+  fn init_state(&self) -> Self::State {
+    Some(0)
+  }
+  fn is_dead(&self, s: &Self::State) -> bool {
+    s.is_none()
+  }
+  fn is_accept(&self, s: &Self::State) -> bool {
+    match s {
+      Some(s) => self.own_pattern[*s].is_some(),
+      None => false,
+    }
+  }
+  fn transition(&self, s: &Self::State, chr: char) -> Self::State {
+    s.and_then(|s| self.goto[s].get(&chr).copied())
+  }
+  // every node of the trie is on the path of a pattern
+  fn is_match_state_reachable(&self, s: &Self::State) -> bool {
+    s.is_some()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn spans(matches: &[Match]) -> Vec<(usize, usize, usize)> {
+    matches.iter().map(|m| (m.pattern, m.start, m.end)).collect()
+  }
+
+  #[test]
+  fn automaton_accepts_patterns() {
+    let ac = AhoCorasick::new(&["he", "she", "his", "hers"]);
+    assert_eq!(ac.states_size(), 10);
+    assert!(ac.test("he"));
+    assert!(ac.test("hers"));
+    assert!(!ac.test("her"));
+    assert!(!ac.test("ushers"));
+    assert!(!ac.test(""));
+  }
+
+  #[test]
+  fn overlapping() {
+    for ac in &[
+      AhoCorasick::new(&["he", "she", "his", "hers"]),
+      AhoCorasick::new(&["he", "she", "his", "hers"]).with_full_dfa(),
+    ] {
+      assert_eq!(
+        spans(&ac.find_overlapping("ushers")),
+        vec![(1, 1, 4), (0, 2, 4), (3, 2, 6)]
+      );
+      assert_eq!(spans(&ac.find_overlapping("ahishe")), vec![(2, 1, 4), (1, 3, 6), (0, 4, 6)]);
+    }
+  }
+
+  #[test]
+  fn leftmost() {
+    for ac in &[
+      AhoCorasick::new(&["abcd", "ab", "bcde", "b"]),
+      AhoCorasick::new(&["abcd", "ab", "bcde", "b"]).with_full_dfa(),
+    ] {
+      assert_eq!(spans(&ac.find_all("xabcde", MatchKind::LeftmostFirst)), vec![(0, 1, 5)]);
+      assert_eq!(spans(&ac.find_all("xabcde", MatchKind::LeftmostLongest)), vec![(0, 1, 5)]);
+      assert_eq!(spans(&ac.find_all("abcbcde", MatchKind::LeftmostLongest)), vec![(1, 0, 2), (2, 3, 7)]);
+    }
+    let ac = AhoCorasick::new(&["Sam", "Samwise"]);
+    assert_eq!(spans(&ac.find_all("Samwise", MatchKind::LeftmostFirst)), vec![(0, 0, 3)]);
+    assert_eq!(spans(&ac.find_all("Samwise", MatchKind::LeftmostLongest)), vec![(1, 0, 7)]);
+    assert_eq!(spans(&ac.find_all("ﬁ Sam", MatchKind::LeftmostFirst)), vec![(0, 4, 7)]);
+  }
+
+  #[test]
+  fn many_keywords() {
+    let words: Vec<String> = (0..3000).map(|i| format!("kw{}x", i)).collect();
+    let patterns: Vec<&str> = words.iter().map(|w| w.as_str()).collect();
+    let ac = AhoCorasick::new(&patterns).with_full_dfa();
+    assert!(ac.test("kw2999x"));
+    assert!(!ac.test("kw3000x"));
+    let found = ac.find_all("a kw12x b kw2999x kw3000x", MatchKind::LeftmostLongest);
+    assert_eq!(spans(&found), vec![(12, 2, 7), (2999, 10, 17)]);
+  }
+}
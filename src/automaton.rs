@@ -1,11 +1,29 @@
 pub trait Automaton {
-  type State;
+  type State: Clone + PartialEq;
 
   fn init_state(&self) -> Self::State;
   fn is_dead(&self, s: &Self::State) -> bool;
   fn is_accept(&self, s: &Self::State) -> bool;
   fn transition(&self, s: &Self::State, chr: char) -> Self::State;
 
+  // false only when no input leads from `s` to an accept state. Searches stop early
+  // on it, automata that keep such states besides the dead one can override it.
+  fn is_match_state_reachable(&self, s: &Self::State) -> bool {
+    !self.is_dead(s)
+  }
+
+  // reads every byte as the char U+0000 to U+00FF, like utf8::bytes_as_chars
+  fn step_bytes(&self, s: &Self::State, bytes: &[u8]) -> Self::State {
+    let mut state = s.clone();
+    for &b in bytes {
+      if self.is_dead(&state) {
+        break;
+      }
+      state = self.transition(&state, b as char);
+    }
+    state
+  }
+
   fn test(&self, s: &str) -> bool {
     let mut state = self.init_state();
     if self.is_dead(&state) {
@@ -19,30 +37,319 @@ pub trait Automaton {
     }
     self.is_accept(&state)
   }
+
+  // byte length of the longest accepted prefix of `s`
+  fn longest_prefix_match(&self, s: &str) -> Option<usize> {
+    let mut res = None;
+    let mut state = self.init_state();
+    let mut chars = s.char_indices();
+    loop {
+      let pos = chars.offset();
+      if self.is_accept(&state) {
+        res = Some(pos);
+      }
+      if !self.is_match_state_reachable(&state) {
+        return res;
+      }
+      match chars.next() {
+        Some((_, chr)) => state = self.transition(&state, chr),
+        None => return res,
+      }
+    }
+  }
+
+  // byte length of the shortest accepted prefix of `s`
+  fn shortest_prefix_match(&self, s: &str) -> Option<usize> {
+    let mut state = self.init_state();
+    let mut chars = s.char_indices();
+    loop {
+      if self.is_accept(&state) {
+        return Some(chars.offset());
+      }
+      if !self.is_match_state_reachable(&state) {
+        return None;
+      }
+      state = self.transition(&state, chars.next()?.1);
+    }
+  }
+
+  // leftmost-longest match starting at byte `start` or later, as a byte range. One pass
+  // runs a thread from every start at once, threads in the same state have the same
+  // future so only the leftmost of them is kept.
+  fn find_at(&self, s: &str, start: usize) -> Option<(usize, usize)> {
+    let start = (start..=s.len()).find(|&idx| s.is_char_boundary(idx))?;
+    let init = self.init_state();
+    let mut threads: Vec<(usize, Self::State)> = vec![]; // ordered by start
+    let mut res: Option<(usize, usize)> = None;
+    let mut chars = s[start..].chars();
+    let mut pos = start;
+    loop {
+      // a thread starting here can't beat a match found already
+      if res.is_none() && !threads.iter().any(|(_, state)| *state == init) {
+        threads.push((pos, init.clone()));
+      }
+      if let Some(&(from, _)) = threads.iter().find(|(_, state)| self.is_accept(state)) {
+        res = Some((from, pos));
+        threads.retain(|&(thread_start, _)| thread_start <= from);
+      }
+      threads.retain(|(_, state)| self.is_match_state_reachable(state));
+      if threads.is_empty() && res.is_some() {
+        return res;
+      }
+      let chr = match chars.next() {
+        Some(chr) => chr,
+        None => return res,
+      };
+      pos += chr.len_utf8();
+      let mut next: Vec<(usize, Self::State)> = vec![];
+      for (from, state) in &threads {
+        let state = self.transition(state, chr);
+        if !next.iter().any(|(_, other)| *other == state) {
+          next.push((*from, state));
+        }
+      }
+      threads = next;
+    }
+  }
 }
 
-struct AutomatonRunner<T: Automaton> {
+// lets runners borrow an automaton instead of owning it
+impl<A: Automaton> Automaton for &A {
+  type State = A::State;
+
+  fn init_state(&self) -> Self::State {
+    A::init_state(self)
+  }
+  fn is_dead(&self, s: &Self::State) -> bool {
+    A::is_dead(self, s)
+  }
+  fn is_accept(&self, s: &Self::State) -> bool {
+    A::is_accept(self, s)
+  }
+  fn transition(&self, s: &Self::State, chr: char) -> Self::State {
+    A::transition(self, s, chr)
+  }
+  fn is_match_state_reachable(&self, s: &Self::State) -> bool {
+    A::is_match_state_reachable(self, s)
+  }
+  fn test(&self, s: &str) -> bool {
+    A::test(self, s)
+  }
+  fn longest_prefix_match(&self, s: &str) -> Option<usize> {
+    A::longest_prefix_match(self, s)
+  }
+  fn shortest_prefix_match(&self, s: &str) -> Option<usize> {
+    A::shortest_prefix_match(self, s)
+  }
+  fn find_at(&self, s: &str, start: usize) -> Option<(usize, usize)> {
+    A::find_at(self, s, start)
+  }
+}
+
+// Resumable matcher: feed the input as it arrives, in chars or chunks, and ask
+// after any of them whether what was read so far matches.
+pub struct AutomatonRunner<T: Automaton> {
   curr_state: T::State,
+  consumed: usize,
   automaton: T,
 }
-   
+
+// where a runner was, to go back there with `restore`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RunnerSnapshot<S> {
+  state: S,
+  consumed: usize,
+}
+
 impl <T: Automaton> AutomatonRunner<T> {
-  fn new(automaton: T) -> Self {
+  pub fn new(automaton: T) -> Self {
     AutomatonRunner {
       curr_state: automaton.init_state(),
+      consumed: 0,
       automaton,
     }
   }
 
-  fn is_dead(&self) -> bool {
+  pub fn automaton(&self) -> &T {
+    &self.automaton
+  }
+
+  pub fn state(&self) -> &T::State {
+    &self.curr_state
+  }
+
+  // the number of chars fed since the start or the last reset
+  pub fn consumed(&self) -> usize {
+    self.consumed
+  }
+
+  pub fn is_dead(&self) -> bool {
     self.automaton.is_dead(&self.curr_state)
   }
  
-  fn is_accept(&self) -> bool {
+  pub fn is_accept(&self) -> bool {
     self.automaton.is_accept(&self.curr_state)
   }
 
-  fn next(&mut self, chr: char) {
-    self.curr_state = self.automaton.transition(&self.curr_state, chr);
+  // false once no further input can lead to a match, as far as
+  // Automaton::is_match_state_reachable knows
+  pub fn can_still_accept(&self) -> bool {
+    self.automaton.is_match_state_reachable(&self.curr_state)
+  }
+
+  pub fn feed_char(&mut self, chr: char) {
+    self.consumed += 1;
+    // the dead state never leaves itself, skip the transition
+    if !self.is_dead() {
+      self.curr_state = self.automaton.transition(&self.curr_state, chr);
+    }
+  }
+
+  pub fn feed(&mut self, chunk: &str) {
+    for chr in chunk.chars() {
+      self.feed_char(chr);
+    }
+  }
+
+  pub fn reset(&mut self) {
+    self.curr_state = self.automaton.init_state();
+    self.consumed = 0;
+  }
+}
+
+impl <T: Automaton> AutomatonRunner<T> {
+  pub fn snapshot(&self) -> RunnerSnapshot<T::State> {
+    RunnerSnapshot {
+      state: self.curr_state.clone(),
+      consumed: self.consumed,
+    }
+  }
+
+  pub fn restore(&mut self, snapshot: RunnerSnapshot<T::State>) {
+    self.curr_state = snapshot.state;
+    self.consumed = snapshot.consumed;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::dfa::DFAOne;
+  use super::super::aho_corasick::AhoCorasick;
+  use super::super::lazy_dfa::LazyDFA;
+  use super::super::nfa::NFAOne;
+
+  // the prefix matches as ranges from 0, then two find_at
+  fn searches<A: Automaton>(automaton: &A) -> [Option<(usize, usize)>; 4] {
+    [
+      automaton.longest_prefix_match("abababx").map(|len| (0, len)),
+      automaton.shortest_prefix_match("abababx").map(|len| (0, len)),
+      automaton.find_at("xxabab", 0),
+      automaton.find_at("abxab", 1),
+    ]
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn default_searches() {
+    let expected = [Some((0, 6)), Some((0, 2)), Some((2, 6)), Some((3, 5))];
+    assert_eq!(searches(&NFAOne::from_regexp("(ab)+")), expected);
+    assert_eq!(searches(&DFAOne::from_regexp("(ab)+", "abx")), expected);
+    assert_eq!(searches(&AhoCorasick::new(&["ab", "abab", "ababab"])), expected);
+    assert_eq!(searches(&LazyDFA::from_regexp("(ab)+")), expected);
+
+    let nullable = NFAOne::from_regexp("a*");
+    assert_eq!(nullable.longest_prefix_match("aab"), Some(2));
+    assert_eq!(nullable.shortest_prefix_match("aab"), Some(0));
+    assert_eq!(nullable.find_at("ba", 0), Some((0, 0)));
+    assert_eq!(nullable.find_at("bé", 3), Some((3, 3)));
+    assert_eq!(NFAOne::from_regexp("é").find_at("aéé", 2), Some((3, 5)));
+    assert_eq!(NFAOne::from_regexp("b").find_at("aaa", 0), None);
+    assert_eq!(NFAOne::from_regexp("a").find_at("éa", 1), Some((2, 3)));
+    assert_eq!(NFAOne::from_regexp("a").find_at("a", 2), None);
+  }
+
+  #[test]
+  fn find_at_prefers_leftmost() {
+    // the match of c ends first, the one of abcd starts first
+    let nfa = NFAOne::from_regexp("abcd|c");
+    assert_eq!(nfa.find_at("abcd", 0), Some((0, 4)));
+    assert_eq!(nfa.find_at("abce", 0), Some((2, 3)));
+    let dfa = DFAOne::from_regexp("a(b|c)*d|b", "abcd");
+    assert_eq!(dfa.find_at("xabcbcx", 0), Some((2, 3)));
+    assert_eq!(dfa.find_at("xabcbcdb", 0), Some((1, 7)));
+  }
+
+  #[test]
+  fn match_state_reachable_overrides() {
+    // state 2 loops on itself without ever accepting
+    let dfa = DFAOne::new(3, Some(0), vec![1], Box::new(|state: usize, chr: char| {
+      match (state, chr) {
+        (0, 'a') => Some(1),
+        (0, 'b') | (2, _) => Some(2),
+        _ => None,
+      }
+    }), "ab");
+    assert!(dfa.is_match_state_reachable(&dfa.init_state()));
+    let state = dfa.transition(&dfa.init_state(), 'b');
+    assert!(!dfa.is_dead(&state) && !dfa.is_match_state_reachable(&state));
+    assert_eq!(dfa.longest_prefix_match("bbbbbbbb"), None);
+    // the $ can't hold before the b
+    let lazy = LazyDFA::from_regexp("a$b|c");
+    let state = lazy.transition(&lazy.init_state(), 'a');
+    assert!(!lazy.is_dead(&state) && !lazy.is_match_state_reachable(&state));
+    let lazy = LazyDFA::from_regexp("(?m)a$\nb");
+    assert!(lazy.is_match_state_reachable(&lazy.transition(&lazy.init_state(), 'a')));
+    let ac = AhoCorasick::new(&["abc"]);
+    assert!(ac.is_match_state_reachable(&ac.transition(&ac.init_state(), 'a')));
+  }
+
+  #[test]
+  fn steps_over_bytes() {
+    let dfa = DFAOne::bytes_from_regexp("é+", false);
+    let state = dfa.step_bytes(&dfa.init_state(), "éé".as_bytes());
+    assert!(dfa.is_accept(&state));
+    let state = dfa.step_bytes(&state, &"é".as_bytes()[..1]);
+    assert!(!dfa.is_accept(&state) && dfa.is_match_state_reachable(&state));
+    let state = dfa.step_bytes(&state, b"e");
+    assert!(!dfa.is_match_state_reachable(&state));
+  }
+
+  #[test]
+  fn runner_feeds_chunks() {
+    let dfa = DFAOne::from_regexp("(a|b)*abb", "ab");
+    let mut runner = AutomatonRunner::new(&dfa);
+    for (chunk, accept) in [("ab", false), ("", false), ("b", true), ("ab", false), ("b", true)] {
+      runner.feed(chunk);
+      assert_eq!(runner.is_accept(), accept, "after {}", chunk);
+    }
+    assert_eq!(runner.consumed(), 6);
+    runner.feed_char('c');
+    assert!(runner.is_dead());
+    assert!(!runner.can_still_accept());
+    runner.feed("abb");
+    assert!(!runner.is_accept());
+    assert_eq!(runner.consumed(), 10);
+    runner.reset();
+    assert_eq!(runner.consumed(), 0);
+    runner.feed("abb");
+    assert!(runner.is_accept());
+  }
+
+  #[test]
+  fn runner_snapshot_restore() {
+    let nfa = NFAOne::from_regexp("(ab)+c");
+    let mut runner = AutomatonRunner::new(nfa);
+    runner.feed("ab");
+    let snapshot = runner.snapshot();
+    runner.feed("c");
+    assert!(runner.is_accept());
+    runner.restore(snapshot.clone());
+    assert_eq!(runner.consumed(), 2);
+    runner.feed("abc");
+    assert!(runner.is_accept());
+    runner.restore(snapshot);
+    runner.feed("b");
+    assert!(runner.is_dead());
+    assert!(runner.automaton().test("abc"));
+  }
+}
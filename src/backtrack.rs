@@ -0,0 +1,294 @@
+use super::char_class::CharClass;
+use super::regexp_parser::{Anchor, Boundary};
+use std::collections::{HashMap, HashSet};
+
+// how many (state, position) pairs `test` explores before giving up
+pub const BACKTRACK_STEP_BUDGET: usize = 1 << 22;
+
+// edges of the Thompson NFA that need more than the current char to be followed
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SpecialEdge {
+  Save(usize), // record the input position in a capture slot
+  Backref(usize), // match the text captured by a group again
+  Look { behind: bool, negated: bool, start: usize, accept: usize }, // zero-width sub-NFA
+  Assert(Anchor), // ^ or $, also used by the regular NFAOne
+  Mark(usize), // record where an iteration of a loop starts
+  Progress(usize), // go around a loop again only if its iteration consumed input
+}
+
+// Thompson NFA with captures, backreferences and lookaround. These aren't regular,
+// so it can only be run by backtracking.
+pub struct BacktrackOne {
+  pub states_size: usize,
+  pub start: usize,
+  pub accept: usize,
+  pub groups: usize,
+  pub loops: usize, // their marks are kept after the capture slots
+  pub reaches_backref: Vec<bool>, // whether the captures can change what a state matches
+  pub transition_map: HashMap<(usize, Option<char>), Vec<usize>>,
+  pub class_map: HashMap<usize, Vec<(CharClass, usize)>>,
+  pub special_map: HashMap<usize, Vec<(SpecialEdge, usize)>>,
+}
+
+enum Job {
+  Explore(usize, usize),
+  Save { slot: usize, state: usize, pos: usize },
+  Restore { slot: usize, old: Option<usize> },
+}
+
+impl BacktrackOne {
+  // the states with a path to a backreference, a lookaround leads into its body
+  pub fn states_reaching_backref(&self) -> Vec<bool> {
+    let mut reverse_edges: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut stack = vec![];
+    for (&(from, _), to_states) in &self.transition_map {
+      for &to in to_states {
+        reverse_edges.entry(to).or_default().push(from);
+      }
+    }
+    for (&from, edges) in &self.class_map {
+      for &(_, to) in edges {
+        reverse_edges.entry(to).or_default().push(from);
+      }
+    }
+    for (&from, edges) in &self.special_map {
+      for &(edge, to) in edges {
+        reverse_edges.entry(to).or_default().push(from);
+        match edge {
+          SpecialEdge::Look { start, .. } => reverse_edges.entry(start).or_default().push(from),
+          SpecialEdge::Backref(_) => stack.push(from),
+          _ => {},
+        }
+      }
+    }
+    let mut res = vec![false; self.states_size];
+    while let Some(state) = stack.pop() {
+      if !res[state] {
+        res[state] = true;
+        stack.extend(reverse_edges.get(&state).into_iter().flatten());
+      }
+    }
+    res
+  }
+
+  fn edges(&self, state: usize, input: Option<char>) -> &[usize] {
+    self.transition_map.get(&(state, input)).map_or(&[], |states| states.as_slice())
+  }
+
+  // whether `start` reaches `accept` reading s[from..end], any end when `end` is None.
+  // `slots` holds the capture positions and loop marks and is restored before returning.
+  // None once `budget` explored pairs are used up, the slots are left as they are then.
+  fn run(
+    &self,
+    s: &[char],
+    (start, accept): (usize, usize),
+    from: usize,
+    end: Option<usize>,
+    slots: &mut [Option<usize>],
+    budget: &mut usize,
+  ) -> Option<bool> {
+    // the captures can't change the outcome of a (state, pos) pair that can't reach a
+    // backreference, so visiting it once is enough. The others are visited once per
+    // content of the slots.
+    let mut visited = vec![false; self.states_size * (s.len() + 1)];
+    let mut visited_with_slots: HashSet<(usize, usize, Vec<Option<usize>>)> = HashSet::new();
+    let mut stack = vec![Job::Explore(start, from)];
+    let mut found = false;
+    while let Some(job) = stack.pop() {
+      let (state, pos) = match job {
+        Job::Explore(state, pos) => (state, pos),
+        Job::Save { slot, state, pos } => {
+          stack.push(Job::Restore { slot, old: slots[slot] });
+          slots[slot] = Some(pos);
+          stack.push(Job::Explore(state, pos));
+          continue;
+        },
+        Job::Restore { slot, old } => {
+          slots[slot] = old;
+          continue;
+        },
+      };
+      if found {
+        continue; // only unwinding the saved slots
+      }
+      if self.reaches_backref[state] {
+        if !visited_with_slots.insert((state, pos, slots.to_vec())) {
+          continue;
+        }
+      } else {
+        let idx = state * (s.len() + 1) + pos;
+        if visited[idx] {
+          continue;
+        }
+        visited[idx] = true;
+      }
+      *budget = budget.checked_sub(1)?;
+      if state == accept && (end.is_none() || end == Some(pos)) {
+        found = true;
+        continue;
+      }
+
+      for &t in self.edges(state, None) {
+        stack.push(Job::Explore(t, pos));
+      }
+      if pos < s.len() {
+        for &t in self.edges(state, Some(s[pos])) {
+          stack.push(Job::Explore(t, pos + 1));
+        }
+        for (class, t) in self.class_map.get(&state).into_iter().flatten() {
+          if class.contains(s[pos]) {
+            stack.push(Job::Explore(*t, pos + 1));
+          }
+        }
+      }
+      for &(edge, t) in self.special_map.get(&state).into_iter().flatten() {
+        match edge {
+          SpecialEdge::Save(slot) => stack.push(Job::Save { slot, state: t, pos }),
+          SpecialEdge::Mark(idx) => stack.push(Job::Save { slot: self.groups * 2 + idx, state: t, pos }),
+          SpecialEdge::Progress(idx) => {
+            if slots[self.groups * 2 + idx] != Some(pos) {
+              stack.push(Job::Explore(t, pos));
+            }
+          },
+          SpecialEdge::Backref(group) => {
+            if let (Some(l), Some(r)) = (slots[group * 2], slots[group * 2 + 1]) {
+              let len = r - l;
+              if pos + len <= s.len() && s[l..r] == s[pos..pos + len] {
+                stack.push(Job::Explore(t, pos + len));
+              }
+            }
+          },
+          SpecialEdge::Assert(anchor) => {
+            let side = if anchor.looks_behind() { pos.checked_sub(1).map(|p| s[p]) } else { s.get(pos).copied() };
+            if anchor.holds(Boundary::of(side)) {
+              stack.push(Job::Explore(t, pos));
+            }
+          },
+          SpecialEdge::Look { behind, negated, start, accept } => {
+            let mut look_slots = slots.to_vec();
+            let matched = if behind {
+              let mut matched = false;
+              for l in 0..=pos {
+                if self.run(s, (start, accept), l, Some(pos), &mut look_slots, budget)? {
+                  matched = true;
+                  break;
+                }
+              }
+              matched
+            } else {
+              self.run(s, (start, accept), pos, None, &mut look_slots, budget)?
+            };
+            if matched != negated {
+              stack.push(Job::Explore(t, pos));
+            }
+          },
+        }
+      }
+    }
+    Some(found)
+  }
+
+  // None when deciding takes more than `budget` explored (state, position) pairs
+  pub fn test_with_step_budget(&self, s: &str, mut budget: usize) -> Option<bool> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut slots = vec![None; self.groups * 2 + self.loops];
+    self.run(&chars, (self.start, self.accept), 0, Some(chars.len()), &mut slots, &mut budget)
+  }
+
+  pub fn test(&self, s: &str) -> bool {
+    self.test_with_step_budget(s, BACKTRACK_STEP_BUDGET)
+      .unwrap_or_else(|| panic!("backtracking gave up after {} steps", BACKTRACK_STEP_BUDGET))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn plain_regexp() {
+    let regexp = BacktrackOne::from_regexp("(a|bc)*abb");
+    assert_eq!(regexp.groups, 2);
+    assert!(regexp.test("abcabb"));
+    assert!(regexp.test("aabb"));
+    assert!(!regexp.test("abcbcabcaabbc"));
+    assert!(!regexp.test("abcbcabbc"));
+  }
+
+  #[test]
+  fn backreference() {
+    let regexp = BacktrackOne::from_regexp("((a|b)*)c\\1");
+    assert!(regexp.test("c"));
+    assert!(regexp.test("abcab"));
+    assert!(regexp.test("babbcbabb"));
+    assert!(!regexp.test("abcba"));
+    assert!(!regexp.test("abcabb"));
+
+    let doubled = BacktrackOne::from_regexp("(a|b)\\1(?:(a|b)\\2)*");
+    assert!(doubled.test("aabbaa"));
+    assert!(!doubled.test("aabab"));
+    // a group that never matched makes its backreference fail
+    assert!(!BacktrackOne::from_regexp("(a)?\\1b").test("b"));
+    // a backreference may come before its group
+    assert!(!BacktrackOne::from_regexp("\\1(a)").test("a"));
+  }
+
+  #[test]
+  #[should_panic(expected = "parse error at 3: backreference \\5 to a missing group")]
+  fn backreference_to_missing_group() {
+    BacktrackOne::from_regexp("(a)\\5");
+  }
+
+  #[test]
+  fn backreference_after_empty_loops() {
+    // these used to hang or take exponential time
+    assert!(!BacktrackOne::from_regexp("(a*)*\\1b").test("aac"));
+    assert!(!BacktrackOne::from_regexp("(a?)*\\1b").test("c"));
+    assert!(!BacktrackOne::from_regexp("(b)?(a|a)*\\1c").test(&"a".repeat(20)));
+    assert!(BacktrackOne::from_regexp("(b)?(a|a)*\\1c").test(&format!("b{}bc", "a".repeat(20))));
+    // one iteration may match the empty string, it just can't go around again
+    assert!(BacktrackOne::from_regexp("(a?)*\\1b").test("b"));
+    assert!(BacktrackOne::from_regexp("(a|())*\\2").test("aa"));
+  }
+
+  #[test]
+  fn step_budget() {
+    let regexp = BacktrackOne::from_regexp("(a|b)*c");
+    assert_eq!(regexp.test_with_step_budget("ababc", 100), Some(true));
+    assert_eq!(regexp.test_with_step_budget(&"ab".repeat(100), 100), None);
+    assert_eq!(regexp.test_with_step_budget(&"ab".repeat(100), 10_000), Some(false));
+  }
+
+  #[test]
+  fn lookahead() {
+    let regexp = BacktrackOne::from_regexp("(?=(a|b)*c)(a|b|c)*");
+    assert!(regexp.test("abc"));
+    assert!(regexp.test("cab"));
+    assert!(!regexp.test("abab"));
+
+    let not_if = BacktrackOne::from_regexp("(?!if)(i|f|x)+");
+    assert!(not_if.test("fi"));
+    assert!(not_if.test("i"));
+    assert!(!not_if.test("if"));
+    assert!(!not_if.test("iff"));
+
+    // the lookahead body is copied with the repeated group
+    let regexp = BacktrackOne::from_regexp("((?!b)(a|b)){2}c");
+    assert!(regexp.test("aac"));
+    assert!(!regexp.test("abc"));
+  }
+
+  #[test]
+  fn lookbehind() {
+    let regexp = BacktrackOne::from_regexp("(a|b)*(?<=ab)c");
+    assert!(regexp.test("abc"));
+    assert!(regexp.test("babc"));
+    assert!(!regexp.test("bbc"));
+    assert!(!regexp.test("c"));
+
+    let regexp = BacktrackOne::from_regexp("(a|b)*(?<!a)");
+    assert!(regexp.test(""));
+    assert!(regexp.test("ab"));
+    assert!(!regexp.test("ba"));
+  }
+}
@@ -0,0 +1,209 @@
+use super::automaton::Automaton;
+use super::char_class::CharClass;
+use super::dfa_regexp::PositionAutomaton;
+use super::nfa::{NFAOne, NFAState};
+use std::collections::HashMap;
+
+// one bit per state of the position automaton, the start state included
+pub const MAX_STATES: usize = 128;
+
+const CHUNK_BITS: usize = 8;
+
+// bit-parallel simulation of the position automaton. Every state entered by a char
+// is entered by the same char, so one step is
+//   next = follow(curr) & char_masks[chr]
+// where follow(curr) is looked up 8 bits at a time from precomputed tables.
+pub struct BitParallelNFA {
+  follow_table: Vec<[u128; 1 << CHUNK_BITS]>,
+  char_masks: HashMap<char, u128>,
+  class_masks: Vec<(CharClass, u128)>, // positions of property classes
+  accept_mask: u128,
+}
+
+impl BitParallelNFA {
+  pub fn from_position_automaton(position_automaton: &PositionAutomaton) -> Self {
+    let states_size = position_automaton.states_size();
+    assert!(states_size <= MAX_STATES, "too many positions for the bit-parallel matcher");
+
+    let follow_mask: Vec<u128> = position_automaton.follow
+      .iter()
+      .map(|to_states| to_states.iter().fold(0, |mask, &to| mask | 1 << to))
+      .collect();
+    let chunks = states_size.div_ceil(CHUNK_BITS);
+    let mut follow_table = vec![[0; 1 << CHUNK_BITS]; chunks];
+    for (chunk, table) in follow_table.iter_mut().enumerate() {
+      for bits in 1usize..(1 << CHUNK_BITS) {
+        // reuse the entry without the lowest bit
+        let low = bits.trailing_zeros() as usize;
+        let state = chunk * CHUNK_BITS + low;
+        let low_follow = if state < states_size { follow_mask[state] } else { 0 };
+        table[bits] = table[bits & (bits - 1)] | low_follow;
+      }
+    }
+
+    let mut char_masks: HashMap<char, u128> = HashMap::new();
+    let mut class_masks: Vec<(CharClass, u128)> = vec![];
+    for (pos, class) in position_automaton.pos_classes.iter().enumerate() {
+      match class.as_single() {
+        Some(chr) => *char_masks.entry(chr).or_insert(0) |= 1 << (pos + 1),
+        None => match class_masks.iter_mut().find(|(c, _)| c == class) {
+          Some((_, mask)) => *mask |= 1 << (pos + 1),
+          None => class_masks.push((class.clone(), 1 << (pos + 1))),
+        },
+      }
+    }
+
+    BitParallelNFA {
+      follow_table,
+      char_masks,
+      class_masks,
+      accept_mask: position_automaton.accept.iter().fold(0, |mask, &s| mask | 1 << s),
+    }
+  }
+
+  pub fn from_regexp(reg_exp: &str) -> Self {
+    BitParallelNFA::from_position_automaton(&PositionAutomaton::from_regexp(reg_exp))
+  }
+
+  fn follow(&self, state: u128) -> u128 {
+    let mut res = 0;
+    for (chunk, table) in self.follow_table.iter().enumerate() {
+      let bits = (state >> (chunk * CHUNK_BITS)) as usize & ((1 << CHUNK_BITS) - 1);
+      res |= table[bits];
+    }
+    res
+  }
+}
+
+impl Automaton for BitParallelNFA {
+  type State = u128;
+
+  fn init_state(&self) -> Self::State {
+    1
+  }
+  fn is_dead(&self, s: &Self::State) -> bool {
+    *s == 0
+  }
+  fn is_accept(&self, s: &Self::State) -> bool {
+    s & self.accept_mask != 0
+  }
+  fn transition(&self, s: &Self::State, chr: char) -> Self::State {
+    let mask = self.class_masks
+      .iter()
+      .filter(|(class, _)| class.contains(chr))
+      .fold(self.char_masks.get(&chr).copied().unwrap_or(0), |mask, (_, class_mask)| mask | class_mask);
+    if mask == 0 { 0 } else { self.follow(*s) & mask }
+  }
+}
+
+// picks the bit-parallel matcher when the pattern has few enough positions,
+// otherwise simulates the Glushkov NFA built from the same positions
+pub enum PositionMatcher {
+  BitParallel(BitParallelNFA),
+  Glushkov(NFAOne),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PositionMatcherState {
+  BitParallel(u128),
+  Glushkov(NFAState),
+}
+
+impl PositionMatcher {
+  pub fn from_regexp(reg_exp: &str) -> Self {
+    let position_automaton = PositionAutomaton::from_regexp(reg_exp);
+    if position_automaton.states_size() <= MAX_STATES {
+      PositionMatcher::BitParallel(BitParallelNFA::from_position_automaton(&position_automaton))
+    } else {
+      PositionMatcher::Glushkov(NFAOne::glushkov_from_position_automaton(&position_automaton))
+    }
+  }
+}
+
+impl Automaton for PositionMatcher {
+  type State = PositionMatcherState;
+
+  fn init_state(&self) -> Self::State {
+    match self {
+      PositionMatcher::BitParallel(m) => PositionMatcherState::BitParallel(m.init_state()),
+      PositionMatcher::Glushkov(m) => PositionMatcherState::Glushkov(m.init_state()),
+    }
+  }
+  fn is_dead(&self, s: &Self::State) -> bool {
+    match (self, s) {
+      (PositionMatcher::BitParallel(m), PositionMatcherState::BitParallel(s)) => m.is_dead(s),
+      (PositionMatcher::Glushkov(m), PositionMatcherState::Glushkov(s)) => m.is_dead(s),
+      _ => panic!("state does not belong to this matcher"),
+    }
+  }
+  fn is_accept(&self, s: &Self::State) -> bool {
+    match (self, s) {
+      (PositionMatcher::BitParallel(m), PositionMatcherState::BitParallel(s)) => m.is_accept(s),
+      (PositionMatcher::Glushkov(m), PositionMatcherState::Glushkov(s)) => m.is_accept(s),
+      _ => panic!("state does not belong to this matcher"),
+    }
+  }
+  fn transition(&self, s: &Self::State, chr: char) -> Self::State {
+    match (self, s) {
+      (PositionMatcher::BitParallel(m), PositionMatcherState::BitParallel(s)) => {
+        PositionMatcherState::BitParallel(m.transition(s, chr))
+      },
+      (PositionMatcher::Glushkov(m), PositionMatcherState::Glushkov(s)) => {
+        PositionMatcherState::Glushkov(Automaton::transition(m, s, chr))
+      },
+      _ => panic!("state does not belong to this matcher"),
+    }
+  }
+
+  fn test(&self, s: &str) -> bool {
+    match self {
+      PositionMatcher::BitParallel(m) => m.test(s),
+      PositionMatcher::Glushkov(m) => m.test(s),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn regexp_instance_1() {
+    let regexp = BitParallelNFA::from_regexp("(a|bc)*abb");
+    assert!(regexp.test("abcabb"));
+    assert!(regexp.test("aabb"));
+    assert!(regexp.test("abcbcabcaabb"));
+    assert!(!regexp.test("abcbcabcaabbc"));
+    assert!(!regexp.test("abcbcabbc"));
+    assert!(!regexp.test(""));
+    assert!(BitParallelNFA::from_regexp("a*").test(""));
+  }
+
+  #[test]
+  fn regexp_number() {
+    let num_exp = BitParallelNFA::from_regexp("((1|2|3|4|5|6|7|8|9)(0|1|2|3|4|5|6|7|8|9)*|0)(\\.(0|1|2|3|4|5|6|7|8|9)+)?");
+    assert!(num_exp.test("0"));
+    assert!(num_exp.test("12.34"));
+    assert!(num_exp.test("1323423"));
+    assert!(!num_exp.test("01323423"));
+    assert!(!num_exp.test("0."));
+    assert!(num_exp.test("0.123"));
+    assert!(!num_exp.test("01.123"));
+  }
+
+  #[test]
+  fn matcher_selection() {
+    // 127 positions fit in the bit masks together with the start state
+    let small = format!("(a|b)*{}", "a".repeat(125));
+    let matcher = PositionMatcher::from_regexp(&small);
+    assert!(matches!(matcher, PositionMatcher::BitParallel(_)));
+    assert!(matcher.test(&format!("bab{}", "a".repeat(125))));
+    assert!(!matcher.test(&"a".repeat(124)));
+
+    let large = format!("(a|b)*{}", "a".repeat(126));
+    let matcher = PositionMatcher::from_regexp(&large);
+    assert!(matches!(matcher, PositionMatcher::Glushkov(_)));
+    assert!(matcher.test(&format!("bab{}", "a".repeat(126))));
+    assert!(!matcher.test(&"a".repeat(125)));
+  }
+}
@@ -0,0 +1,275 @@
+use super::unicode_tables::{GENERAL_CATEGORY, SCRIPT, XID_CONTINUE, XID_START};
+
+pub use super::unicode_tables::UNICODE_VERSION;
+
+// set of chars as sorted, non-overlapping and non-adjacent inclusive ranges
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CharClass {
+  ranges: Vec<(char, char)>,
+  name: Option<String>, // the \p{..} it was written as, for printing
+}
+
+// the general categories that group the two letter ones starting with the same letter
+const MAJOR_CATEGORY: &[(&str, &str)] = &[
+  ("C", "Other"),
+  ("L", "Letter"),
+  ("M", "Mark"),
+  ("N", "Number"),
+  ("P", "Punctuation"),
+  ("S", "Symbol"),
+  ("Z", "Separator"),
+];
+
+fn next_char(chr: char) -> Option<char> {
+  match chr {
+    '\u{D7FF}' => Some('\u{E000}'),
+    char::MAX => None,
+    _ => char::from_u32(chr as u32 + 1),
+  }
+}
+
+fn prev_char(chr: char) -> Option<char> {
+  match chr {
+    '\u{E000}' => Some('\u{D7FF}'),
+    '\0' => None,
+    _ => char::from_u32(chr as u32 - 1),
+  }
+}
+
+// UAX #44 loose matching: case, spaces, underscores and hyphens are ignored
+fn loose_eq(a: &str, b: &str) -> bool {
+  let normalize = |s: &str| -> String {
+    s.chars().filter(|c| !matches!(c, ' ' | '_' | '-')).flat_map(char::to_lowercase).collect()
+  };
+  normalize(a) == normalize(b)
+}
+
+impl CharClass {
+  pub fn new(mut ranges: Vec<(char, char)>) -> Self {
+    ranges.retain(|(lo, hi)| lo <= hi);
+    ranges.sort_unstable();
+    let mut merged: Vec<(char, char)> = vec![];
+    for (lo, hi) in ranges {
+      match merged.last_mut() {
+        Some(last) if next_char(last.1).is_none_or(|next| lo <= next) => last.1 = last.1.max(hi),
+        _ => merged.push((lo, hi)),
+      }
+    }
+    CharClass { ranges: merged, name: None }
+  }
+
+  pub fn single(chr: char) -> Self {
+    CharClass { ranges: vec![(chr, chr)], name: None }
+  }
+
+  pub fn ranges(&self) -> &[(char, char)] {
+    &self.ranges
+  }
+
+  pub fn name(&self) -> Option<&str> {
+    self.name.as_deref()
+  }
+
+  // the same chars, printed as `name`
+  pub fn with_name(self, name: String) -> CharClass {
+    CharClass { name: Some(name), ..self }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.ranges.is_empty()
+  }
+
+  // the only char of the class, if it has exactly one
+  pub fn as_single(&self) -> Option<char> {
+    match self.ranges.as_slice() {
+      [(lo, hi)] if lo == hi => Some(*lo),
+      _ => None,
+    }
+  }
+
+  pub fn contains(&self, chr: char) -> bool {
+    self.ranges
+      .binary_search_by(|&(lo, hi)| {
+        if hi < chr {
+          std::cmp::Ordering::Less
+        } else if lo > chr {
+          std::cmp::Ordering::Greater
+        } else {
+          std::cmp::Ordering::Equal
+        }
+      })
+      .is_ok()
+  }
+
+  pub fn union(&self, other: &CharClass) -> CharClass {
+    CharClass::new(self.ranges.iter().chain(&other.ranges).copied().collect())
+  }
+
+  pub fn negate(&self) -> CharClass {
+    let mut res = vec![];
+    let mut lo = Some('\0');
+    for &(range_lo, range_hi) in &self.ranges {
+      if let (Some(l), Some(hi)) = (lo, prev_char(range_lo)) {
+        res.push((l, hi));
+      }
+      lo = next_char(range_hi);
+    }
+    if let Some(l) = lo {
+      res.push((l, char::MAX));
+    }
+    CharClass::new(res)
+  }
+
+  // the class as a comma separated list of code points and code point ranges,
+  // like "U+30-U+39,U+5F", which from_property reads back
+  pub fn code_point_list(&self) -> String {
+    let fmt_range = |&(lo, hi): &(char, char)| match lo == hi {
+      true => format!("U+{:X}", lo as u32),
+      false => format!("U+{:X}-U+{:X}", lo as u32, hi as u32),
+    };
+    self.ranges.iter().map(fmt_range).collect::<Vec<_>>().join(",")
+  }
+
+  fn from_code_point_list(list: &str) -> Option<CharClass> {
+    let code_point = |s: &str| char::from_u32(u32::from_str_radix(s.strip_prefix("U+")?, 16).ok()?);
+    let ranges = list
+      .split(',')
+      .map(|range| match range.split_once('-') {
+        Some((lo, hi)) => Some((code_point(lo)?, code_point(hi)?)),
+        None => code_point(range).map(|chr| (chr, chr)),
+      })
+      .collect::<Option<Vec<_>>>()?;
+    Some(CharClass::new(ranges))
+  }
+
+  // a general category (short or long name, "L" for all the letters), a script,
+  // XID_Start/XID_Continue optionally prefixed by "gc=" or "sc=", or a code point list
+  pub fn from_property(name: &str) -> Option<CharClass> {
+    if name.starts_with("U+") {
+      return CharClass::from_code_point_list(name);
+    }
+    let (prop, value) = match name.split_once('=') {
+      Some((prop, value)) => (Some(prop), value),
+      None => (None, name),
+    };
+    let is_prop = |names: &[&str]| prop.is_none_or(|p| names.iter().any(|n| loose_eq(p, n)));
+    let from_table = |ranges: &[(char, char)]| CharClass { ranges: ranges.to_vec(), name: None };
+
+    if is_prop(&["gc", "General_Category"]) {
+      for &(short, long, ranges) in GENERAL_CATEGORY {
+        if loose_eq(value, short) || loose_eq(value, long) {
+          return Some(from_table(ranges));
+        }
+      }
+      for &(short, long) in MAJOR_CATEGORY {
+        if loose_eq(value, short) || loose_eq(value, long) {
+          let ranges = GENERAL_CATEGORY
+            .iter()
+            .filter(|(sub, _, _)| sub.starts_with(short))
+            .flat_map(|(_, _, ranges)| ranges.iter().copied())
+            .collect();
+          return Some(CharClass::new(ranges));
+        }
+      }
+    }
+    if is_prop(&["sc", "Script"]) {
+      for &(short, long, ranges) in SCRIPT {
+        if loose_eq(value, short) || loose_eq(value, long) {
+          return Some(from_table(ranges));
+        }
+      }
+    }
+    if prop.is_none() {
+      if loose_eq(value, "XID_Start") || loose_eq(value, "XIDS") {
+        return Some(from_table(XID_START));
+      }
+      if loose_eq(value, "XID_Continue") || loose_eq(value, "XIDC") {
+        return Some(from_table(XID_CONTINUE));
+      }
+      if loose_eq(value, "Any") {
+        return Some(CharClass::new(vec![('\0', char::MAX)]));
+      }
+    }
+    None
+  }
+}
+
+// the class of a "\p{name}", "\pN", "\P{name}" or "\PN" written in a pattern
+pub fn parse_property(written: &str) -> CharClass {
+  let negated = written.starts_with("\\P");
+  let name = &written[2..];
+  let name = name.strip_prefix('{').and_then(|n| n.strip_suffix('}')).unwrap_or(name);
+  let class = CharClass::from_property(name)
+    .unwrap_or_else(|| panic!("unknown unicode property {}", name));
+  let class = if negated { class.negate() } else { class };
+  if name.starts_with("U+") {
+    return class; // already spelled out by its ranges
+  }
+  class.with_name(written.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::automaton::Automaton;
+  use super::super::backtrack::BacktrackOne;
+  use super::super::bit_parallel::BitParallelNFA;
+  use super::super::derivative::DerivativeOne;
+  use super::super::dfa::DFAOne;
+  use super::super::nfa::NFAOne;
+
+  #[test]
+  fn class_ops() {
+    let class = CharClass::new(vec![('d', 'f'), ('a', 'b'), ('c', 'c'), ('x', 'z'), ('y', 'y')]);
+    assert_eq!(class.ranges(), &[('a', 'f'), ('x', 'z')]);
+    assert!(class.contains('e'));
+    assert!(!class.contains('g'));
+    assert_eq!(class.negate().ranges(), &[('\0', '`'), ('g', 'w'), ('{', char::MAX)]);
+    assert_eq!(class.negate().negate(), class);
+    assert_eq!(CharClass::new(vec![('\u{D000}', '\u{D7FF}'), ('\u{E000}', '\u{E001}')]).ranges().len(), 1);
+    assert_eq!(CharClass::new(vec![]).negate().ranges(), &[('\0', char::MAX)]);
+  }
+
+  #[test]
+  fn properties() {
+    let letter = CharClass::from_property("L").unwrap();
+    assert!(letter.contains('a') && letter.contains('Ж') && letter.contains('中'));
+    assert!(!letter.contains('1') && !letter.contains('_'));
+    assert_eq!(CharClass::from_property("Letter"), Some(letter));
+    let upper = CharClass::from_property("gc=uppercase letter").unwrap();
+    assert!(upper.contains('Ω') && !upper.contains('ω'));
+    let greek = CharClass::from_property("Greek").unwrap();
+    assert_eq!(CharClass::from_property("sc=Grek"), Some(greek.clone()));
+    assert!(greek.contains('λ') && !greek.contains('l'));
+    assert!(CharClass::from_property("XID_Start").unwrap().contains('é'));
+    assert!(!CharClass::from_property("XID_Start").unwrap().contains('٣'));
+    assert!(CharClass::from_property("XID_Continue").unwrap().contains('٣'));
+    assert!(CharClass::from_property("Nd").unwrap().contains('٣'));
+    assert_eq!(CharClass::from_property("Klingon"), None);
+    assert_eq!(CharClass::from_property("sc=Lu"), None);
+  }
+
+  #[test]
+  fn regexp_properties() {
+    let ident = "\\p{XID_Start}\\p{XID_Continue}*";
+    let nfa = NFAOne::from_regexp(ident);
+    let dfa = DFAOne::from_regexp(ident, "aé1_ ٣");
+    let bit_parallel = BitParallelNFA::from_regexp(ident);
+    let glushkov = NFAOne::glushkov_from_regexp(ident);
+    let derivative = DerivativeOne::from_regexp(ident);
+    let backtrack = BacktrackOne::from_regexp(ident);
+    for (s, expected) in [("aé1_", true), ("é٣", true), ("1a", false), ("_a", false), ("a a", false), ("", false)] {
+      assert_eq!(nfa.test(s), expected, "{}", s);
+      assert_eq!(dfa.test(s), expected, "{}", s);
+      assert_eq!(bit_parallel.test(s), expected, "{}", s);
+      assert_eq!(glushkov.test(s), expected, "{}", s);
+      assert_eq!(derivative.test(s), expected, "{}", s);
+      assert_eq!(backtrack.test(s), expected, "{}", s);
+    }
+
+    let not_greek = NFAOne::from_regexp("\\P{Greek}+|\\pN");
+    assert!(not_greek.test("abc"));
+    assert!(not_greek.test("7"));
+    assert!(!not_greek.test("aλ"));
+  }
+}
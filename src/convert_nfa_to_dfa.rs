@@ -24,6 +24,9 @@ pub fn convert_nfa_to_dfa(nfa: NFAOne, input_set: &str) -> DFAOne {
     is_marked[curr_state_idx] = true;
     for chr in input_set.chars() {
       let new_state = nfa.e_closure(nfa.transition(&curr_state, chr));
+      if new_state.is_empty() {
+        continue; // empty NFA state set is the dead state, leave it as None
+      }
       let new_state_idx = match new_state_map.get(&new_state) {
         Some(idx) => *idx,
         None => {
@@ -50,14 +53,15 @@ pub fn convert_nfa_to_dfa(nfa: NFAOne, input_set: &str) -> DFAOne {
     None
   }).collect();
 
-  DFAOne {
-    states_size: new_state_idx,
-    start: Some(0),
+  DFAOne::new(
+    new_state_idx,
+    Some(0),
     accept,
-    transition_func: Box::new(move |s: usize, chr: char| {
-      transition_map.get(&(s, chr)).map(|s| *s)
-    })
-  }
+    Box::new(move |s: usize, chr: char| {
+      transition_map.get(&(s, chr)).copied()
+    }),
+    input_set,
+  )
 }
 
 #[cfg(test)]
@@ -94,4 +98,20 @@ mod tests {
     assert!(!converted_dfa.test("abbb"));
     assert!(converted_dfa.test("abababaabb"));
   }
+
+  #[test]
+  fn states_size_and_dead_state() {
+    // (a|b)*abb gives the five states A-E of the textbook subset construction
+    let converted_dfa = convert_nfa_to_dfa(NFAOne::from_regexp("(a|b)*abb"), "ab");
+    assert_eq!(converted_dfa.states_size, 5);
+    assert_eq!(converted_dfa.state_minimization("ab").states_size, 4);
+
+    let converted_dfa = convert_nfa_to_dfa(NFAOne::from_regexp("abc"), "abc");
+    assert_eq!(converted_dfa.states_size, 4);
+    assert_eq!(converted_dfa.transition(&converted_dfa.init_state(), 'c'), None);
+    let min_dfa = converted_dfa.state_minimization("abc");
+    assert_eq!(min_dfa.states_size, 4);
+    assert!(min_dfa.test("abc"));
+    assert!(!min_dfa.test("abcc"));
+  }
 }
@@ -0,0 +1,347 @@
+use super::automaton::Automaton;
+use super::dfa::DFAOne;
+use super::dfa_regexp::{parse_ast_regexp, NodeType, RegASTNode};
+use super::language_ops;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+// smart constructors, they keep every derivative in a normal form (up to associativity,
+// commutativity and idempotence of | and &) so there are finitely many of them
+
+fn is_empty_set(node: &RegASTNode) -> bool {
+  *node == RegASTNode::LeafEmptySet
+}
+
+fn universal() -> RegASTNode {
+  mk_unary(NodeType::Complement, RegASTNode::LeafEmptySet)
+}
+
+fn mk_unary(node_type: NodeType, child: RegASTNode) -> RegASTNode {
+  RegASTNode::Unary { node_type, child: Box::new(child) }
+}
+
+fn mk_binary(node_type: NodeType, left: RegASTNode, right: RegASTNode) -> RegASTNode {
+  RegASTNode::Binary { node_type, left_child: Box::new(left), right_child: Box::new(right) }
+}
+
+fn flatten(node_type: NodeType, node: RegASTNode, operands: &mut Vec<RegASTNode>) {
+  match node {
+    RegASTNode::Binary { node_type: t, left_child, right_child } if t == node_type => {
+      flatten(node_type, *left_child, operands);
+      flatten(node_type, *right_child, operands);
+    },
+    node => operands.push(node),
+  }
+}
+
+// sorted, deduplicated and right nested operands of | or &
+fn mk_aci(node_type: NodeType, left: RegASTNode, right: RegASTNode) -> RegASTNode {
+  let mut operands = vec![];
+  flatten(node_type, left, &mut operands);
+  flatten(node_type, right, &mut operands);
+  let (unit, zero) = match node_type {
+    NodeType::Union => (RegASTNode::LeafEmptySet, universal()),
+    NodeType::Intersect => (universal(), RegASTNode::LeafEmptySet),
+    _ => unreachable!(),
+  };
+  if operands.contains(&zero) {
+    return zero;
+  }
+  operands.retain(|node| *node != unit);
+  operands.sort();
+  operands.dedup();
+  match operands.pop() {
+    None => unit,
+    Some(last) => operands
+      .into_iter()
+      .rev()
+      .fold(last, |acc, node| mk_binary(node_type, node, acc)),
+  }
+}
+
+fn mk_union(left: RegASTNode, right: RegASTNode) -> RegASTNode {
+  mk_aci(NodeType::Union, left, right)
+}
+
+fn mk_intersect(left: RegASTNode, right: RegASTNode) -> RegASTNode {
+  mk_aci(NodeType::Intersect, left, right)
+}
+
+fn mk_concat(left: RegASTNode, right: RegASTNode) -> RegASTNode {
+  match (left, right) {
+    (RegASTNode::LeafEmptySet, _) | (_, RegASTNode::LeafEmptySet) => RegASTNode::LeafEmptySet,
+    (RegASTNode::LeafEmpty, node) | (node, RegASTNode::LeafEmpty) => node,
+    (RegASTNode::Binary { node_type: NodeType::Concat, left_child, right_child }, right) => {
+      mk_concat(*left_child, mk_concat(*right_child, right))
+    },
+    (left, right) => mk_binary(NodeType::Concat, left, right),
+  }
+}
+
+fn mk_closure(child: RegASTNode) -> RegASTNode {
+  match child {
+    RegASTNode::LeafEmpty | RegASTNode::LeafEmptySet => RegASTNode::LeafEmpty,
+    node @ RegASTNode::Unary { node_type: NodeType::Closure, .. } => node,
+    node => mk_unary(NodeType::Closure, node),
+  }
+}
+
+fn mk_complement(child: RegASTNode) -> RegASTNode {
+  match child {
+    RegASTNode::Unary { node_type: NodeType::Complement, child } => *child,
+    node => mk_unary(NodeType::Complement, node),
+  }
+}
+
+// rebuild the tree through the smart constructors, the end marker stands for e
+pub fn normalize(node: &RegASTNode) -> RegASTNode {
+  match node {
+    RegASTNode::Endmarker | RegASTNode::LeafEmpty => RegASTNode::LeafEmpty,
+    RegASTNode::LeafEmptySet => RegASTNode::LeafEmptySet,
+    RegASTNode::Leaf(_) | RegASTNode::Class(_) => node.clone(),
+    RegASTNode::Anchor(_) => panic!("^ and $ are only supported by DFAOne, NFAOne and BacktrackOne"),
+    RegASTNode::Unary { node_type, child } => match node_type {
+      NodeType::Closure => mk_closure(normalize(child)),
+      NodeType::Complement => mk_complement(normalize(child)),
+      _ => normalize(&node.desugar()),
+    },
+    RegASTNode::Binary { node_type, left_child, right_child } => {
+      let (left, right) = (normalize(left_child), normalize(right_child));
+      match node_type {
+        NodeType::Concat => mk_concat(left, right),
+        NodeType::Union => mk_union(left, right),
+        NodeType::Intersect => mk_intersect(left, right),
+        _ => unreachable!(),
+      }
+    },
+  }
+}
+
+pub fn nullable(node: &RegASTNode) -> bool {
+  match node {
+    RegASTNode::Endmarker | RegASTNode::LeafEmpty => true,
+    // an anchor matches the empty string only where it holds
+    RegASTNode::LeafEmptySet | RegASTNode::Leaf(_) | RegASTNode::Class(_) | RegASTNode::Anchor(_) => false,
+    RegASTNode::Unary { node_type, child } => match node_type {
+      NodeType::Closure | NodeType::Question => true,
+      NodeType::Complement => !nullable(child),
+      NodeType::Plus => nullable(child),
+      NodeType::Repeat { min, .. } => *min == 0 || nullable(child),
+      _ => unreachable!(),
+    },
+    RegASTNode::Binary { node_type, left_child, right_child } => match node_type {
+      NodeType::Concat | NodeType::Intersect => nullable(left_child) && nullable(right_child),
+      NodeType::Union => nullable(left_child) || nullable(right_child),
+      _ => unreachable!(),
+    },
+  }
+}
+
+// the language { w | chr w in L(node) }
+pub fn derivative(node: &RegASTNode, chr: char) -> RegASTNode {
+  match node {
+    RegASTNode::Endmarker | RegASTNode::LeafEmpty | RegASTNode::LeafEmptySet => RegASTNode::LeafEmptySet,
+    RegASTNode::Leaf(c) => if *c == chr { RegASTNode::LeafEmpty } else { RegASTNode::LeafEmptySet },
+    RegASTNode::Class(class) => if class.contains(chr) { RegASTNode::LeafEmpty } else { RegASTNode::LeafEmptySet },
+    RegASTNode::Anchor(_) => panic!("^ and $ are only supported by DFAOne, NFAOne and BacktrackOne"),
+    RegASTNode::Unary { node_type, child } => match node_type {
+      NodeType::Closure => mk_concat(derivative(child, chr), node.clone()),
+      NodeType::Complement => mk_complement(derivative(child, chr)),
+      _ => derivative(&node.desugar(), chr),
+    },
+    RegASTNode::Binary { node_type, left_child, right_child } => match node_type {
+      NodeType::Concat => {
+        let left_part = mk_concat(derivative(left_child, chr), (**right_child).clone());
+        if nullable(left_child) {
+          mk_union(left_part, derivative(right_child, chr))
+        } else {
+          left_part
+        }
+      },
+      NodeType::Union => mk_union(derivative(left_child, chr), derivative(right_child, chr)),
+      NodeType::Intersect => mk_intersect(derivative(left_child, chr), derivative(right_child, chr)),
+      _ => unreachable!(),
+    },
+  }
+}
+
+pub struct DerivativeOne {
+  pub ast: RegASTNode,
+}
+
+impl DerivativeOne {
+  pub fn from_regexp(reg_exp: &str) -> Self {
+    DerivativeOne {
+      ast: normalize(&parse_ast_regexp(reg_exp)),
+    }
+  }
+
+  // builds the whole DFA whose states are the derivatives reachable over `input`
+  pub fn to_dfa(&self, input: &str) -> DFAOne {
+    language_ops::determinize(self, input)
+  }
+
+  pub fn lazy_dfa(&self) -> LazyDerivativeDFA {
+    LazyDerivativeDFA::new(self.ast.clone())
+  }
+}
+
+impl Automaton for DerivativeOne {
+  type State = RegASTNode;
+
+  fn init_state(&self) -> Self::State {
+    self.ast.clone()
+  }
+  fn is_dead(&self, s: &Self::State) -> bool {
+    is_empty_set(s)
+  }
+  fn is_accept(&self, s: &Self::State) -> bool {
+    nullable(s)
+  }
+  fn transition(&self, s: &Self::State, chr: char) -> Self::State {
+    derivative(s, chr)
+  }
+}
+
+struct DerivativeCache {
+  states: Vec<RegASTNode>,
+  states_idx_map: HashMap<RegASTNode, usize>,
+  transition_map: HashMap<(usize, char), usize>,
+}
+
+// DFA over derivatives, each state and edge is computed the first time it is walked
+pub struct LazyDerivativeDFA {
+  cache: RefCell<DerivativeCache>,
+}
+
+impl LazyDerivativeDFA {
+  fn new(ast: RegASTNode) -> Self {
+    let mut states_idx_map = HashMap::new();
+    states_idx_map.insert(ast.clone(), 0);
+    LazyDerivativeDFA {
+      cache: RefCell::new(DerivativeCache {
+        states: vec![ast],
+        states_idx_map,
+        transition_map: HashMap::new(),
+      }),
+    }
+  }
+
+  pub fn states_size(&self) -> usize {
+    self.cache.borrow().states.len()
+  }
+}
+
+impl Automaton for LazyDerivativeDFA {
+  type State = usize;
+
+  fn init_state(&self) -> Self::State {
+    0
+  }
+  fn is_dead(&self, s: &Self::State) -> bool {
+    is_empty_set(&self.cache.borrow().states[*s])
+  }
+  fn is_accept(&self, s: &Self::State) -> bool {
+    nullable(&self.cache.borrow().states[*s])
+  }
+  fn transition(&self, s: &Self::State, chr: char) -> Self::State {
+    let mut cache = self.cache.borrow_mut();
+    if let Some(&t) = cache.transition_map.get(&(*s, chr)) {
+      return t;
+    }
+    let new_state = derivative(&cache.states[*s], chr);
+    let t = match cache.states_idx_map.get(&new_state) {
+      Some(&idx) => idx,
+      None => {
+        let idx = cache.states.len();
+        cache.states.push(new_state.clone());
+        cache.states_idx_map.insert(new_state, idx);
+        idx
+      },
+    };
+    cache.transition_map.insert((*s, chr), t);
+    t
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::nfa::NFAOne;
+
+  fn all_strings(input: &str, max_len: usize) -> Vec<String> {
+    let mut res = vec![String::new()];
+    let mut last = vec![String::new()];
+    for _ in 0..max_len {
+      last = last
+        .iter()
+        .flat_map(|s| input.chars().map(move |chr| format!("{}{}", s, chr)))
+        .collect();
+      res.extend(last.iter().cloned());
+    }
+    res
+  }
+
+  #[test]
+  fn cross_check_engines() {
+    let patterns = [
+      ("(a|b)*abb", "ab"),
+      ("(a|bc)*abb", "abc"),
+      ("(a*b*)*c?", "abc"),
+      ("a(b|c)+a", "abc"),
+      ("((a|b)(a|b))*", "ab"),
+      ("(ab|a)(bc|c)", "abc"),
+      ("()", "ab"),
+    ];
+    for &(pattern, input) in &patterns {
+      let nfa = NFAOne::from_regexp(pattern);
+      let dfa = DFAOne::from_regexp(pattern, input);
+      let deriv = DerivativeOne::from_regexp(pattern);
+      let deriv_dfa = deriv.to_dfa(input);
+      let lazy = deriv.lazy_dfa();
+      for s in all_strings(input, 6) {
+        let expected = nfa.test(&s);
+        assert_eq!(dfa.test(&s), expected, "{} on {:?}", pattern, s);
+        assert_eq!(deriv.test(&s), expected, "{} on {:?}", pattern, s);
+        assert_eq!(deriv_dfa.test(&s), expected, "{} on {:?}", pattern, s);
+        assert_eq!(lazy.test(&s), expected, "{} on {:?}", pattern, s);
+      }
+    }
+  }
+
+  #[test]
+  fn extended_operators() {
+    let pattern = "(a|b)*&~((a|b)*bb(a|b)*)";
+    let dfa = DFAOne::from_regexp(pattern, "ab");
+    let deriv = DerivativeOne::from_regexp(pattern);
+    for s in all_strings("ab", 6) {
+      assert_eq!(deriv.test(&s), dfa.test(&s), "{:?}", s);
+    }
+    assert_eq!(deriv.to_dfa("ab").state_minimization("ab").states_size, 2);
+  }
+
+  #[test]
+  fn derivatives_are_finite() {
+    // without normalisation the derivatives of (a|b)*abb grow without bound
+    let deriv = DerivativeOne::from_regexp("(a|b)*abb");
+    assert_eq!(deriv.to_dfa("ab").states_size, 4);
+
+    let lazy = deriv.lazy_dfa();
+    assert_eq!(lazy.states_size(), 1);
+    assert!(lazy.test("ababababb"));
+    assert_eq!(lazy.states_size(), 4);
+  }
+
+  #[test]
+  fn smart_constructors() {
+    let a = RegASTNode::Leaf('a');
+    let b = RegASTNode::Leaf('b');
+    assert_eq!(mk_union(b.clone(), mk_union(a.clone(), b.clone())), mk_union(a.clone(), b.clone()));
+    assert_eq!(mk_union(a.clone(), RegASTNode::LeafEmptySet), a);
+    assert_eq!(mk_intersect(a.clone(), universal()), a);
+    assert_eq!(mk_concat(RegASTNode::LeafEmpty, a.clone()), a);
+    assert_eq!(mk_concat(a.clone(), RegASTNode::LeafEmptySet), RegASTNode::LeafEmptySet);
+    assert_eq!(mk_closure(mk_closure(a.clone())), mk_closure(a.clone()));
+    assert_eq!(mk_complement(mk_complement(a.clone())), a);
+  }
+}
@@ -6,6 +6,7 @@ pub struct DFAOne {
   pub start: Option<usize>,
   pub accept: Vec<usize>,
   pub transition_func: Box<dyn Fn(usize, char) -> Option<usize>>,
+  pub co_reachable: Vec<bool>, // whether an accept state can be reached from each state
 }
 
 struct Partition {
@@ -55,7 +56,35 @@ fn has_at_least_two_elements(color: &[usize]) -> bool {
 }
 
 impl DFAOne {
-  fn state_minimization(&self, input: &str) -> DFAOne {
+  // `transition_func` is only looked at on the chars of `input`, to find the states
+  // that can still reach an accept state
+  pub fn new(
+    states_size: usize,
+    start: Option<usize>,
+    accept: Vec<usize>,
+    transition_func: Box<dyn Fn(usize, char) -> Option<usize>>,
+    input: &str,
+  ) -> DFAOne {
+    let mut from_states: Vec<Vec<usize>> = vec![vec![]; states_size];
+    for s in 0..states_size {
+      for chr in input.chars() {
+        if let Some(t) = transition_func(s, chr) {
+          from_states[t].push(s);
+        }
+      }
+    }
+    let mut co_reachable = vec![false; states_size];
+    let mut stack = accept.clone();
+    while let Some(curr) = stack.pop() {
+      if !co_reachable[curr] {
+        co_reachable[curr] = true;
+        stack.extend(&from_states[curr]);
+      }
+    }
+    DFAOne { states_size, start, accept, transition_func, co_reachable }
+  }
+
+  pub fn state_minimization(&self, input: &str) -> DFAOne {
     let mut parti = Partition::new(self.states_size + 1); // one more state for the dead one
     // first partition accorinding to accept
     let mut new_color = vec![0; self.states_size + 1];
@@ -97,51 +126,47 @@ impl DFAOne {
     }
 
     let mut new_transition_map: HashMap<char, Vec<Option<usize>>> = HashMap::new();
-    
-    // generate old_state => new_state map (omitting dead state)
+
+    // generate group => new_state map, the group holding the dead state maps to None
+    let dead_group = parti.which_group(dead_state_id);
     let mut new_state_old_represent = vec![];
+    let mut group_new_state_map = vec![None; parti.group_ids_map.len()];
     for (group, old_ids) in parti.group_ids_map.iter().enumerate() {
-      if old_ids[0] == dead_state_id {
+      if group == dead_group {
         continue;
       }
+      group_new_state_map[group] = Some(new_state_old_represent.len());
       new_state_old_represent.push(old_ids[0]);
     }
-    let dead_group = parti.which_group(dead_state_id);
-    let enhanced_which_group = |old_s: usize| {
-      let w = parti.which_group(old_s);
-      if w > dead_group {
-        w - 1
-      } else {
-        w
-      }
-    };
+    let enhanced_which_group = |old_s: usize| group_new_state_map[parti.which_group(old_s)];
     let new_accept: Vec<_> = self.accept
       .iter()
-      .map(|&s| enhanced_which_group(s))
+      .filter_map(|&s| enhanced_which_group(s))
       .collect::<HashSet<_>>()
       .into_iter()
       .collect();
-    let new_start = self.start.map(|i| enhanced_which_group(i));
+    let new_start = self.start.and_then(enhanced_which_group);
 
     for chr in input.chars() {
       let ts: Vec<_> = new_state_old_represent
         .iter()
         .map(|&old_rs| {
-          (self.transition_func)(old_rs, chr).map(|old_ts| enhanced_which_group(old_ts))
+          (self.transition_func)(old_rs, chr).and_then(enhanced_which_group)
         })
         .collect();
       new_transition_map.insert(chr, ts);
     }
 
 
-    DFAOne {
-      states_size: new_state_old_represent.len(),
-      start: new_start,
-      accept: new_accept,
-      transition_func: Box::new(move |s: usize, chr: char| {
+    DFAOne::new(
+      new_state_old_represent.len(),
+      new_start,
+      new_accept,
+      Box::new(move |s: usize, chr: char| {
         new_transition_map.get(&chr).and_then(|to_s_map| to_s_map[s])
-      })
-    }
+      }),
+      input,
+    )
   }
 }
 
@@ -162,6 +187,9 @@ impl Automaton for DFAOne {
   fn transition(&self, s: &Self::State, chr: char) -> Self::State {
     s.and_then(|s| (self.transition_func)(s, chr))
   }
+  fn is_match_state_reachable(&self, s: &Self::State) -> bool {
+    s.is_some_and(|s| self.co_reachable[s])
+  }
 }
 
 #[cfg(test)]
@@ -171,18 +199,19 @@ mod tests {
   #[test]
   fn test_instance_1() {
     // (a|b)*abb
-    let dfa = DFAOne {
-      states_size: 4,
-      start: Some(0),
-      accept: vec![3],
-      transition_func: Box::new(|state: usize, chr: char| {
+    let dfa = DFAOne::new(
+      4,
+      Some(0),
+      vec![3],
+      Box::new(|state: usize, chr: char| {
         match chr {
           'a' => Some([1,1,1,1][state]),
           'b' => Some([0,2,3,0][state]),
           _ => None,
         }
       }),
-    };
+      "ab",
+    );
     assert!(dfa.test("aabb"));
     assert!(!dfa.test("abbb"));
     assert!(dfa.test("abb"));
@@ -204,18 +233,19 @@ mod tests {
 
   #[test]
   fn state_minimization_works() {
-    let dfa = DFAOne {
-      states_size: 5,
-      start: Some(0),
-      accept: vec![4],
-      transition_func: Box::new(|state: usize, chr: char| {
+    let dfa = DFAOne::new(
+      5,
+      Some(0),
+      vec![4],
+      Box::new(|state: usize, chr: char| {
         match chr {
           'a' => Some([1,1,1,1,1][state]),
           'b' => Some([2,3,2,4,2][state]),
           _ => None,
         }
       }),
-    };
+      "ab",
+    );
     let min_dfa = dfa.state_minimization("ab");
     assert_eq!(min_dfa.states_size, 4);
     assert!(min_dfa.test("aabb"));
@@ -230,5 +260,27 @@ mod tests {
     assert!(minmin_dfa.test("abb"));
     assert!(minmin_dfa.test("abababaabb"));
   }
-}
 
+  #[test]
+  fn state_minimization_drops_dead_states() {
+    // state 2 can never reach the accept state, so it merges into the dead state
+    let dfa = DFAOne::new(
+      3,
+      Some(0),
+      vec![1],
+      Box::new(|state: usize, chr: char| {
+        match (state, chr) {
+          (0, 'a') => Some(1),
+          (0, 'b') => Some(2),
+          (2, _) => Some(2),
+          _ => None,
+        }
+      }),
+      "ab",
+    );
+    let min_dfa = dfa.state_minimization("ab");
+    assert_eq!(min_dfa.states_size, 2);
+    assert!(min_dfa.test("a"));
+    assert_eq!(min_dfa.transition(&min_dfa.init_state(), 'b'), None);
+  }
+}
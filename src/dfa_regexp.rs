@@ -1,147 +1,78 @@
-use super::regop::RegOp;
+use super::char_class::CharClass;
 use super::dfa::DFAOne;
+use super::nfa::NFAOne;
+use super::language_ops;
 use std::collections::{HashSet, HashMap};
-use super::escape_chars::{EscapeChars, MaybeEsc};
+use std::fmt;
+use super::regexp_parser::{self, Anchor, Ast, AstKind, Boundary, GroupKind};
+use super::flags::Flags;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum NodeType {
   Closure,
   Concat,
   Union,
+  Intersect,
+  Complement,
+  Plus,
+  Question,
+  Repeat { min: usize, max: Option<usize> }, // max is None for {m,}
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum RegASTNode {
   Endmarker,
   LeafEmpty,
+  LeafEmptySet, // matches nothing, never produced by the parser
   Leaf(char),
+  Class(CharClass), // any char of a property like \p{L}, never a single char
+  Anchor(Anchor), // ^ or $, matches the empty string where it holds
   Unary { node_type: NodeType, child: Box<RegASTNode> },
   Binary { node_type: NodeType, left_child: Box<RegASTNode>, right_child: Box<RegASTNode> },
 }
 
-struct StackFrame {
-  op_stack: Vec<RegOp>,
-  item_stack: Vec<RegASTNode>,
+// the regular subset of the parsed syntax, groups are dropped
+fn lower(ast: &Ast) -> RegASTNode {
+  match &ast.kind {
+    AstKind::Empty => RegASTNode::LeafEmpty,
+    AstKind::Char(_) | AstKind::Class(_) => {
+      let class = ast.leaf_class().unwrap();
+      match class.as_single() {
+        Some(chr) => RegASTNode::Leaf(chr),
+        None => RegASTNode::Class(class),
+      }
+    },
+    AstKind::Anchor(anchor) => RegASTNode::Anchor(*anchor),
+    AstKind::Backref(group) => panic!("backreference \\{} is not regular, use BacktrackOne::from_regexp", group),
+    AstKind::Group { kind: GroupKind::Look { .. }, .. } => {
+      panic!("lookaround is not regular, use BacktrackOne::from_regexp")
+    },
+    AstKind::Group { child, .. } => lower(child),
+    AstKind::Unary { node_type, child } => RegASTNode::Unary {
+      node_type: *node_type,
+      child: Box::new(lower(child)),
+    },
+    AstKind::List { node_type, children } => children
+      .iter()
+      .map(lower)
+      .reduce(|left, right| RegASTNode::Binary {
+        node_type: *node_type,
+        left_child: Box::new(left),
+        right_child: Box::new(right),
+      })
+      .unwrap(),
+  }
 }
 
 pub fn parse_ast_regexp(reg_exp: &str) -> RegASTNode {
-  let mut stack: Vec<StackFrame> = vec![];
-  let mut curr_stackframe = StackFrame {
-    op_stack: vec![],
-    item_stack: vec![]
-  };
-
-  fn push_new_op(curr_stackframe: &mut StackFrame, new_op: RegOp) {
-    while let Some(top_op) = curr_stackframe.op_stack.pop() {
-      if top_op.get_priority() < new_op.get_priority() {
-        curr_stackframe.op_stack.push(top_op); // push back, top_op has lower priority
-        break;
-      }
-      match top_op {
-        RegOp::Eof | RegOp::Paren => {}, // do nothing
-        RegOp::Union | RegOp::Concat => { // binary operator
-          let operand_right = curr_stackframe.item_stack.pop().expect(&format!("parse fail at {:?}", top_op));
-          let operand_left = curr_stackframe.item_stack.pop().expect(&format!("parse fail at {:?}", top_op));
-          curr_stackframe.item_stack.push(
-            RegASTNode::Binary {
-              node_type: match top_op {
-               RegOp::Union => NodeType::Union,
-                RegOp::Concat => NodeType::Concat,
-                _ => unreachable!(),
-              },
-              left_child: Box::new(operand_left),
-              right_child: Box::new(operand_right),
-            }
-          );
-        },
-        RegOp::Closure | RegOp::Plus | RegOp::Question => {
-          let operand = curr_stackframe.item_stack.pop().expect(&format!("parse fail at {:?}", top_op));
-          curr_stackframe.item_stack.push(
-            match top_op {
-              RegOp::Closure => RegASTNode::Unary {
-                node_type: NodeType::Closure,
-                child: Box::new(operand),
-              },
-              RegOp::Plus => RegASTNode::Binary {
-                node_type: NodeType::Concat,
-                left_child: Box::new(operand.clone()),
-                right_child: Box::new(RegASTNode::Unary {
-                  node_type: NodeType::Closure,
-                  child: Box::new(operand),
-                })
-              },
-              RegOp::Question => RegASTNode::Binary {
-                node_type: NodeType::Union,
-                left_child: Box::new(RegASTNode::LeafEmpty),
-                right_child: Box::new(operand),
-              },
-              _ => unreachable!(),
-            }
-          );
-        },
-      }
-    }
-    curr_stackframe.op_stack.push(new_op);
-  }
-
-  let mut is_last_item = false;
-  for chr in EscapeChars::new(reg_exp.chars()) {
-    match chr {
-      MaybeEsc::NonEsc('(') => {
-        if is_last_item { push_new_op(&mut curr_stackframe, RegOp::Concat); }
-        stack.push(curr_stackframe);
-        curr_stackframe = StackFrame {
-          op_stack: vec![RegOp::Paren],
-          item_stack: vec![]
-        };
-        is_last_item = false;
-      },
-      MaybeEsc::NonEsc(')') => {
-        push_new_op(&mut curr_stackframe, RegOp::Paren);
-        assert!(curr_stackframe.item_stack.len() <= 1, "current frame error");
-        let frame_res = if curr_stackframe.item_stack.is_empty() {
-          RegASTNode::LeafEmpty
-        } else {
-          curr_stackframe.item_stack.pop().unwrap()
-        };
-        curr_stackframe = stack.pop().expect("stack empty error");
-        curr_stackframe.item_stack.push(frame_res);
-        is_last_item = true;
-      },
-      MaybeEsc::NonEsc('|') => {
-        push_new_op(&mut curr_stackframe, RegOp::Union);
-        is_last_item = false;
-      },
-      MaybeEsc::NonEsc('*')
-      | MaybeEsc::NonEsc('?')
-      | MaybeEsc::NonEsc('+') => {
-        push_new_op(&mut curr_stackframe, match chr.get_chr() {
-          '*' => RegOp::Closure,
-          '?' => RegOp::Question,
-          '+' => RegOp::Plus,
-          _ => unreachable!(),
-        });
-        is_last_item = true;
-      },
-      maybe_esc_chr => {
-        let chr = maybe_esc_chr.get_chr();
-        if is_last_item { push_new_op(&mut curr_stackframe, RegOp::Concat); }
-        curr_stackframe.item_stack.push(RegASTNode::Leaf(chr));
-        is_last_item = true;
-      },
-    }
-  }
+  parse_ast_regexp_with_flags(reg_exp, Flags::default())
+}
 
-  assert!(stack.is_empty(), "parse error: stack should be reduced to 1");
-  push_new_op(&mut curr_stackframe, RegOp::Eof);
-  assert!(curr_stackframe.item_stack.len() <= 1, "parse error: res itme should <= 1");
+pub fn parse_ast_regexp_with_flags(reg_exp: &str, flags: Flags) -> RegASTNode {
+  let ast = regexp_parser::parse_with_flags(reg_exp, flags);
   RegASTNode::Binary {
     node_type: NodeType::Concat,
-    left_child: Box::new(if curr_stackframe.item_stack.is_empty() {
-      RegASTNode::LeafEmpty
-    } else {
-      curr_stackframe.item_stack.pop().unwrap()
-    }),
+    left_child: Box::new(lower(&ast)),
     right_child: Box::new(RegASTNode::Endmarker),
   }
 }
@@ -150,8 +81,9 @@ pub fn parse_ast_regexp(reg_exp: &str) -> RegASTNode {
 struct DFABuilder {
   pos_idx: usize,
   follow_pos: Vec<HashSet<usize>>,
-  pos_char_map: Vec<char>,
+  pos_char_map: Vec<CharClass>,
   end_idx: Option<usize>,
+  anchors: HashMap<usize, Anchor>, // positions read without consuming a char
 }
 
 impl DFABuilder {
@@ -161,6 +93,7 @@ impl DFABuilder {
       follow_pos: vec![],
       pos_char_map: vec![],
       end_idx: None,
+      anchors: HashMap::new(),
     }
   }
 
@@ -171,9 +104,9 @@ impl DFABuilder {
     res
   }
 
-  fn acquire_new_pos_idx(&mut self, chr: char) -> usize {
+  fn acquire_new_pos_idx(&mut self, class: CharClass) -> usize {
     let new_idx = self.gen_new_idx();
-    self.pos_char_map.push(chr);
+    self.pos_char_map.push(class);
     new_idx
   }
 
@@ -181,7 +114,13 @@ impl DFABuilder {
     let new_idx = self.gen_new_idx();
     assert!(self.end_idx.is_none(), "end index can only be set once");
     self.end_idx = Some(new_idx);
-    self.pos_char_map.push('\0');
+    self.pos_char_map.push(CharClass::new(vec![]));
+    new_idx
+  }
+
+  fn acquire_anchor_pos_idx(&mut self, anchor: Anchor) -> usize {
+    let new_idx = self.acquire_new_pos_idx(CharClass::new(vec![]));
+    self.anchors.insert(new_idx, anchor);
     new_idx
   }
 
@@ -221,58 +160,323 @@ impl TraverseInfo {
 
 
 fn set_union(set_a: Vec<usize>, set_b: Vec<usize>) -> Vec<usize> {
-  set_a.into_iter().chain(set_b.into_iter()).collect::<HashSet<usize>>().into_iter().collect()
+  set_a.into_iter().chain(set_b).collect::<HashSet<usize>>().into_iter().collect()
 }
 
-impl DFAOne {
-  pub fn from_regexp(reg_exp: &str, input: &str) -> DFAOne {
-    fn traverse_ast(node: &RegASTNode, builder: &mut DFABuilder) -> TraverseInfo {
-      match node {
-        RegASTNode::LeafEmpty => TraverseInfo::new_empty(),
-        RegASTNode::Leaf(chr) => TraverseInfo::new_singleton(builder.acquire_new_pos_idx(*chr)),
-        RegASTNode::Endmarker => TraverseInfo::new_singleton(builder.acquire_end_pos_idx()),
-        RegASTNode::Unary { node_type, ref child } => {
-          let child_info = traverse_ast(child, builder);
-          match node_type {
-            NodeType::Closure => {
-              builder.register_new_follow_pos(&child_info.last_pos, &child_info.first_pos);
-              TraverseInfo { nullable: true, ..child_info }
-            },
-            _ => unreachable!(),
-          }
+fn traverse_ast(node: &RegASTNode, builder: &mut DFABuilder) -> TraverseInfo {
+  match node {
+    RegASTNode::LeafEmpty => TraverseInfo::new_empty(),
+    RegASTNode::LeafEmptySet => TraverseInfo { nullable: false, ..TraverseInfo::new_empty() },
+    RegASTNode::Leaf(chr) => TraverseInfo::new_singleton(builder.acquire_new_pos_idx(CharClass::single(*chr))),
+    RegASTNode::Class(class) => TraverseInfo::new_singleton(builder.acquire_new_pos_idx(class.clone())),
+    RegASTNode::Anchor(anchor) => TraverseInfo::new_singleton(builder.acquire_anchor_pos_idx(*anchor)),
+    RegASTNode::Endmarker => TraverseInfo::new_singleton(builder.acquire_end_pos_idx()),
+    RegASTNode::Unary { node_type: NodeType::Repeat { .. }, .. } => traverse_ast(&node.desugar(), builder),
+    RegASTNode::Unary { node_type, ref child } => {
+      let child_info = traverse_ast(child, builder);
+      match node_type {
+        NodeType::Closure => {
+          builder.register_new_follow_pos(&child_info.last_pos, &child_info.first_pos);
+          TraverseInfo { nullable: true, ..child_info }
         },
-        RegASTNode::Binary { node_type, ref left_child, ref right_child } => {
-          let left_info = traverse_ast(left_child, builder);
-          let right_info = traverse_ast(right_child, builder);
-          match node_type {
-            NodeType::Concat => {
-              builder.register_new_follow_pos(&left_info.last_pos, &right_info.first_pos);
-              TraverseInfo {
-                nullable: left_info.nullable && right_info.nullable,
-                first_pos: set_union(
-                  left_info.first_pos,
-                  if left_info.nullable { right_info.first_pos } else { vec![] }
-                ),
-                last_pos: set_union(
-                  right_info.last_pos,
-                  if right_info.nullable { left_info.last_pos } else { vec![] }
-                ),
-              }
-            },
-            NodeType::Union => TraverseInfo {
-              nullable: left_info.nullable || right_info.nullable,
-              first_pos: set_union(left_info.first_pos, right_info.first_pos),
-              last_pos: set_union(left_info.last_pos, right_info.last_pos),
-            },
-            _ => unreachable!(),
+        NodeType::Plus => {
+          builder.register_new_follow_pos(&child_info.last_pos, &child_info.first_pos);
+          child_info
+        },
+        NodeType::Question => TraverseInfo { nullable: true, ..child_info },
+        _ => unreachable!(),
+      }
+    },
+    RegASTNode::Binary { node_type, ref left_child, ref right_child } => {
+      let left_info = traverse_ast(left_child, builder);
+      let right_info = traverse_ast(right_child, builder);
+      match node_type {
+        NodeType::Concat => {
+          builder.register_new_follow_pos(&left_info.last_pos, &right_info.first_pos);
+          TraverseInfo {
+            nullable: left_info.nullable && right_info.nullable,
+            first_pos: set_union(
+              left_info.first_pos,
+              if left_info.nullable { right_info.first_pos } else { vec![] }
+            ),
+            last_pos: set_union(
+              right_info.last_pos,
+              if right_info.nullable { left_info.last_pos } else { vec![] }
+            ),
           }
         },
+        NodeType::Union => TraverseInfo {
+          nullable: left_info.nullable || right_info.nullable,
+          first_pos: set_union(left_info.first_pos, right_info.first_pos),
+          last_pos: set_union(left_info.last_pos, right_info.last_pos),
+        },
+        _ => unreachable!(),
       }
+    },
+  }
+}
+
+impl RegASTNode {
+  // whether the followpos construction can't handle this tree
+  fn has_extended_op(&self) -> bool {
+    match self {
+      RegASTNode::Endmarker | RegASTNode::LeafEmpty | RegASTNode::LeafEmptySet | RegASTNode::Leaf(_)
+        | RegASTNode::Class(_) | RegASTNode::Anchor(_) => false,
+      RegASTNode::Unary { node_type: NodeType::Complement, .. } => true,
+      RegASTNode::Binary { node_type: NodeType::Intersect, .. } => true,
+      RegASTNode::Unary { child, .. } => child.has_extended_op(),
+      RegASTNode::Binary { left_child, right_child, .. } => {
+        left_child.has_extended_op() || right_child.has_extended_op()
+      },
+    }
+  }
+
+  fn has_anchor(&self) -> bool {
+    match self {
+      RegASTNode::Anchor(_) => true,
+      RegASTNode::Unary { child, .. } => child.has_anchor(),
+      RegASTNode::Binary { left_child, right_child, .. } => left_child.has_anchor() || right_child.has_anchor(),
+      _ => false,
+    }
+  }
+
+  fn without_endmarker(&self) -> &RegASTNode {
+    match self {
+      RegASTNode::Binary { node_type: NodeType::Concat, left_child, right_child }
+        if **right_child == RegASTNode::Endmarker => left_child,
+      _ => self,
+    }
+  }
+
+  fn with_endmarker(&self) -> RegASTNode {
+    RegASTNode::Binary {
+      node_type: NodeType::Concat,
+      left_child: Box::new(self.clone()),
+      right_child: Box::new(RegASTNode::Endmarker),
+    }
+  }
+
+  // matches the strings of `self` spelled backwards, a root endmarker stays at the end
+  pub fn reverse(&self) -> RegASTNode {
+    let body = self.without_endmarker();
+    if !std::ptr::eq(body, self) {
+      return body.reverse().with_endmarker();
+    }
+    match self {
+      RegASTNode::Unary { node_type, child } => RegASTNode::Unary {
+        node_type: *node_type,
+        child: Box::new(child.reverse()),
+      },
+      RegASTNode::Binary { node_type: NodeType::Concat, .. } => {
+        // reverse the whole chain of factors so it stays nested to the left like the parser builds it
+        let mut factors = vec![];
+        let mut node = self;
+        while let RegASTNode::Binary { node_type: NodeType::Concat, left_child, right_child } = node {
+          factors.push(right_child.reverse());
+          node = left_child;
+        }
+        factors.push(node.reverse());
+        factors
+          .into_iter()
+          .reduce(|left, right| RegASTNode::Binary {
+            node_type: NodeType::Concat,
+            left_child: Box::new(left),
+            right_child: Box::new(right),
+          })
+          .unwrap()
+      },
+      // reversing is a bijection on strings, so it commutes with |, & and ~
+      RegASTNode::Binary { node_type, left_child, right_child } => RegASTNode::Binary {
+        node_type: *node_type,
+        left_child: Box::new(left_child.reverse()),
+        right_child: Box::new(right_child.reverse()),
+      },
+      RegASTNode::Anchor(anchor) => RegASTNode::Anchor(anchor.reverse()),
+      _ => self.clone(),
+    }
+  }
+
+  // the root +, ? or {m,n} spelled with concat, union and closure, the children are
+  // kept as they are. Any other node is returned unchanged.
+  pub fn desugar(&self) -> RegASTNode {
+    let concat = |left: RegASTNode, right: RegASTNode| RegASTNode::Binary {
+      node_type: NodeType::Concat,
+      left_child: Box::new(left),
+      right_child: Box::new(right),
+    };
+    let closure = |child: &RegASTNode| RegASTNode::Unary {
+      node_type: NodeType::Closure,
+      child: Box::new(child.clone()),
+    };
+    let optional = |child: &RegASTNode| RegASTNode::Binary {
+      node_type: NodeType::Union,
+      left_child: Box::new(RegASTNode::LeafEmpty),
+      right_child: Box::new(child.clone()),
+    };
+    match self {
+      RegASTNode::Unary { node_type: NodeType::Plus, child } => concat((**child).clone(), closure(child)),
+      RegASTNode::Unary { node_type: NodeType::Question, child } => optional(child),
+      RegASTNode::Unary { node_type: NodeType::Repeat { min, max }, child } => {
+        let mut pieces = vec![(**child).clone(); *min];
+        match max {
+          None => pieces.push(closure(child)),
+          Some(max) => pieces.extend((*min..*max).map(|_| optional(child))),
+        }
+        pieces.into_iter().reduce(concat).unwrap_or(RegASTNode::LeafEmpty)
+      },
+      _ => self.clone(),
+    }
+  }
+
+  // how tightly the printed node binds, a child printed with less than its parent
+  // asks for gets parenthesized
+  fn precedence(&self) -> u8 {
+    match self {
+      RegASTNode::Binary { node_type: NodeType::Concat, left_child, right_child }
+        if **right_child == RegASTNode::Endmarker => left_child.precedence(),
+      RegASTNode::Binary { node_type: NodeType::Union, .. } => 1,
+      RegASTNode::Binary { node_type: NodeType::Intersect, .. } => 2,
+      RegASTNode::Binary { .. } => 3,
+      RegASTNode::Unary { node_type: NodeType::Complement, .. } => 4,
+      RegASTNode::Unary { .. } => 5,
+      _ => 6,
     }
+  }
 
+  fn fmt_prec(&self, f: &mut fmt::Formatter, min_prec: u8) -> fmt::Result {
+    if self.precedence() < min_prec {
+      write!(f, "(")?;
+      self.fmt_prec(f, 0)?;
+      return write!(f, ")");
+    }
+    match self {
+      RegASTNode::Endmarker => Ok(()),
+      RegASTNode::LeafEmpty => write!(f, "()"),
+      RegASTNode::LeafEmptySet => write!(f, "(~()&())"), // no syntax of its own
+      RegASTNode::Leaf(chr) => fmt_char(f, *chr),
+      RegASTNode::Anchor(anchor) => write!(f, "{}", match anchor {
+        Anchor::StartText => "^",
+        Anchor::StartLine => "(?m:^)",
+        Anchor::EndLine => "(?m:$)",
+        Anchor::EndText => "$",
+      }),
+      RegASTNode::Class(class) => match class.name() {
+        Some(name) => write!(f, "{}", name),
+        None if class.is_empty() => RegASTNode::LeafEmptySet.fmt_prec(f, min_prec),
+        // spelled out by its ranges, or those of its negation when that has fewer
+        None => {
+          let negated = class.negate();
+          if negated.ranges().len() < class.ranges().len() {
+            write!(f, "\\P{{{}}}", negated.code_point_list())
+          } else {
+            write!(f, "\\p{{{}}}", class.code_point_list())
+          }
+        },
+      },
+      RegASTNode::Unary { node_type: NodeType::Complement, child } => {
+        write!(f, "~")?;
+        child.fmt_prec(f, 4)
+      },
+      RegASTNode::Unary { node_type, child } => {
+        child.fmt_prec(f, 5)?;
+        match node_type {
+          NodeType::Closure => write!(f, "*"),
+          NodeType::Plus => write!(f, "+"),
+          NodeType::Question => write!(f, "?"),
+          NodeType::Repeat { min, max: None } => write!(f, "{{{},}}", min),
+          NodeType::Repeat { min, max: Some(max) } if min == max => write!(f, "{{{}}}", min),
+          NodeType::Repeat { min, max: Some(max) } => write!(f, "{{{},{}}}", min, max),
+          _ => unreachable!(),
+        }
+      },
+      RegASTNode::Binary { node_type: NodeType::Concat, left_child, right_child }
+        if **right_child == RegASTNode::Endmarker => left_child.fmt_prec(f, min_prec),
+      RegASTNode::Binary { node_type, left_child, right_child } => {
+        let (prec, op) = match node_type {
+          NodeType::Union => (1, "|"),
+          NodeType::Intersect => (2, "&"),
+          NodeType::Concat => (3, ""),
+          _ => unreachable!(),
+        };
+        // the parser is left associative, so a right child of the same kind needs parens
+        left_child.fmt_prec(f, prec)?;
+        write!(f, "{}", op)?;
+        right_child.fmt_prec(f, prec + 1)
+      },
+    }
+  }
+}
+
+fn fmt_char(f: &mut fmt::Formatter, chr: char) -> fmt::Result {
+  if "()|*+?&~\\{}.^$".contains(chr) {
+    write!(f, "\\")?;
+  }
+  write!(f, "{}", chr)
+}
+
+// prints a pattern that parses back to the same tree, with as few parens as possible
+impl fmt::Display for RegASTNode {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    self.fmt_prec(f, 0)
+  }
+}
+
+impl DFAOne {
+  pub fn from_regexp(reg_exp: &str, input: &str) -> DFAOne {
+    DFAOne::from_regexp_ast(&parse_ast_regexp(reg_exp), input)
+  }
+
+  // for trees built or rewritten by hand, the end marker is optional
+  pub fn from_regexp_ast(ast: &RegASTNode, input: &str) -> DFAOne {
+    DFAOne::from_extended_ast(ast.without_endmarker(), input)
+  }
+
+  // accepts the matches of `reg_exp` read from end to start, see language_ops::match_start
+  pub fn reverse_from_regexp(reg_exp: &str, input: &str) -> DFAOne {
+    DFAOne::from_regexp_ast(&parse_ast_regexp(reg_exp).reverse(), input)
+  }
+
+  pub fn from_regexp_with_flags(reg_exp: &str, input: &str, flags: Flags) -> DFAOne {
+    DFAOne::from_regexp_ast(&parse_ast_regexp_with_flags(reg_exp, flags), input)
+  }
+
+  // intersection and complement are not expressible with followpos, so subtrees holding
+  // them are combined from the DFAs of their children by product construction
+  fn from_extended_ast(node: &RegASTNode, input: &str) -> DFAOne {
+    if !node.has_extended_op() && node.has_anchor() {
+      return DFAOne::from_anchored_ast(&node.with_endmarker(), input);
+    }
+    if !node.has_extended_op() {
+      return DFAOne::from_ast(&node.with_endmarker(), input);
+    }
+    // the DFAs of the operands would each see their own text start and end
+    assert!(!node.has_anchor(), "^ and $ can't be combined with & and ~");
+    match node {
+      RegASTNode::Unary { node_type: NodeType::Complement, child } => {
+        language_ops::complement(&DFAOne::from_extended_ast(child, input), input)
+      },
+      RegASTNode::Unary { node_type: NodeType::Closure, child } => {
+        language_ops::closure(&DFAOne::from_extended_ast(child, input), input)
+      },
+      RegASTNode::Unary { .. } => DFAOne::from_extended_ast(&node.desugar(), input),
+      RegASTNode::Binary { node_type, left_child, right_child } => {
+        let left_dfa = DFAOne::from_extended_ast(left_child, input);
+        let right_dfa = DFAOne::from_extended_ast(right_child, input);
+        match node_type {
+          NodeType::Concat => language_ops::concat(&left_dfa, &right_dfa, input),
+          NodeType::Union => language_ops::union(&left_dfa, &right_dfa, input),
+          NodeType::Intersect => language_ops::intersection(&left_dfa, &right_dfa, input),
+          _ => unreachable!(),
+        }
+      },
+      _ => unreachable!(), // leaves never hold extended operators
+    }
+  }
+
+  // followpos construction, `ast` must end with the end marker
+  fn from_ast(ast: &RegASTNode, input: &str) -> DFAOne {
     let mut dfa_builder = DFABuilder::new();
-    let ast = parse_ast_regexp(reg_exp);
-    let root_info = traverse_ast(&ast, &mut dfa_builder);
+    let root_info = traverse_ast(ast, &mut dfa_builder);
     let end_idx = dfa_builder.end_idx.expect("invalid end marker");
 
 
@@ -293,7 +497,7 @@ impl DFAOne {
     state_idx += 1;
 
     while let Some(curr_states) = stack.pop() {
-      let &curr_idx = states_idx_map.get(&curr_states).expect(&format!("curr states {:?} unregistered", curr_states));
+      let &curr_idx = states_idx_map.get(&curr_states).unwrap_or_else(|| panic!("curr states {:?} unregistered", curr_states));
       if is_marked[curr_idx] {
         continue;
       }
@@ -301,8 +505,7 @@ impl DFAOne {
       for chr in input.chars() {
         let mut res_states = vec![];
         for &pos in &curr_states {
-          let pos_chr = dfa_builder.pos_char_map[pos];
-          if pos_chr == chr {
+          if dfa_builder.pos_char_map[pos].contains(chr) {
             for &fpos in &dfa_builder.follow_pos[pos] {
               res_states.push(fpos);
             }
@@ -313,6 +516,9 @@ impl DFAOne {
           res_states.dedup();
           res_states
         };
+        if new_states.is_empty() {
+          continue; // empty position set is the dead state, leave it as None
+        }
         let new_state_idx = match states_idx_map.get(&new_states) {
           None => {
             let new_state_idx = state_idx;
@@ -335,12 +541,161 @@ impl DFAOne {
       }
     }).collect();
 
-    DFAOne {
-      states_size: dfa_builder.pos_idx,
-      start: Some(0),
+    DFAOne::new(
+      state_idx,
+      Some(0),
+      accept,
+      Box::new(move |s: usize, chr: char| {
+        transition_map.get(&(s, chr)).copied()
+      }),
+      input,
+    )
+  }
+}
+
+impl DFAOne {
+  // followpos construction reading the ^ and $ positions without consuming a char. A
+  // state is the set of char positions reached, each with the strictest $ that is
+  // still waiting for the next char.
+  fn from_anchored_ast(ast: &RegASTNode, input: &str) -> DFAOne {
+    let mut dfa_builder = DFABuilder::new();
+    let root_info = traverse_ast(ast, &mut dfa_builder);
+    let end_idx = dfa_builder.end_idx.expect("invalid end marker");
+    let builder = &dfa_builder;
+
+    // follow the anchors that hold, with `before` the boundary before the position
+    let close = |mut stack: Vec<(usize, Option<Anchor>)>, before: Boundary| {
+      let mut seen: HashSet<(usize, Option<Anchor>)> = stack.iter().copied().collect();
+      let mut res = vec![];
+      while let Some((pos, pending)) = stack.pop() {
+        let pending = match builder.anchors.get(&pos) {
+          None => {
+            res.push((pos, pending));
+            continue;
+          },
+          Some(anchor) if anchor.looks_behind() && !anchor.holds(before) => continue,
+          Some(anchor) if anchor.looks_behind() => pending,
+          Some(&anchor) => pending.max(Some(anchor)),
+        };
+        for &fpos in &builder.follow_pos[pos] {
+          if seen.insert((fpos, pending)) {
+            stack.push((fpos, pending));
+          }
+        }
+      }
+      res.sort_unstable();
+      res
+    };
+
+    let init = close(root_info.first_pos.iter().map(|&pos| (pos, None)).collect(), Boundary::Text);
+    language_ops::build_dfa(
+      init,
+      |states, chr| {
+        let after = Boundary::of(Some(chr));
+        let next = states
+          .iter()
+          .filter(|(pos, pending)| {
+            pending.is_none_or(|anchor| anchor.holds(after)) && builder.pos_char_map[*pos].contains(chr)
+          })
+          .flat_map(|&(pos, _)| builder.follow_pos[pos].iter().map(|&fpos| (fpos, None)))
+          .collect();
+        close(next, after)
+      },
+      |states| states.is_empty(),
+      // the text ends here, so every $ holds
+      |states| states.iter().any(|&(pos, _)| pos == end_idx),
+      input,
+    )
+  }
+}
+
+// position (Glushkov) automaton: state 0 is the start and state p + 1 stands for
+// symbol position p, so n symbol occurrences give n + 1 states and no epsilon edges
+pub struct PositionAutomaton {
+  pub pos_classes: Vec<CharClass>, // the chars read when entering state p + 1
+  pub follow: Vec<Vec<usize>>, // sorted successor states of every state
+  pub accept: Vec<usize>,
+}
+
+impl PositionAutomaton {
+  pub fn from_regexp(reg_exp: &str) -> Self {
+    PositionAutomaton::from_ast(&parse_ast_regexp(reg_exp))
+  }
+
+  // the end marker of `ast` is optional
+  pub fn from_ast(ast: &RegASTNode) -> Self {
+    let ast = ast.without_endmarker().with_endmarker();
+    assert!(!ast.has_extended_op(), "& and ~ are only supported by DFAOne::from_regexp");
+    assert!(!ast.has_anchor(), "^ and $ are only supported by DFAOne, NFAOne and BacktrackOne");
+    let mut builder = DFABuilder::new();
+    let root_info = traverse_ast(&ast, &mut builder);
+    let end_idx = builder.end_idx.expect("invalid end marker");
+
+    // the end marker is the last position and gets no state
+    let to_states = |positions: &mut dyn Iterator<Item = &usize>| {
+      let mut states: Vec<usize> = positions.filter(|&&pos| pos != end_idx).map(|pos| pos + 1).collect();
+      states.sort_unstable();
+      states
+    };
+    let mut follow = vec![to_states(&mut root_info.first_pos.iter())];
+    for pos in 0..end_idx {
+      follow.push(to_states(&mut builder.follow_pos[pos].iter()));
+    }
+    let mut accept: Vec<usize> = (0..end_idx)
+      .filter(|&pos| builder.follow_pos[pos].contains(&end_idx))
+      .map(|pos| pos + 1)
+      .collect();
+    if root_info.first_pos.contains(&end_idx) {
+      accept.insert(0, 0);
+    }
+
+    PositionAutomaton {
+      pos_classes: builder.pos_char_map[..end_idx].to_vec(),
+      follow,
       accept,
-      transition_func: Box::new(move |s: usize, chr: char| {
-        transition_map.get(&(s, chr)).map(|to_s| *to_s)
+    }
+  }
+
+  pub fn states_size(&self) -> usize {
+    self.follow.len()
+  }
+}
+
+impl NFAOne {
+  pub fn glushkov_from_regexp(reg_exp: &str) -> NFAOne {
+    NFAOne::glushkov_from_position_automaton(&PositionAutomaton::from_regexp(reg_exp))
+  }
+
+  pub fn glushkov_from_position_automaton(position_automaton: &PositionAutomaton) -> NFAOne {
+    let mut transition_map: HashMap<(usize, char), Vec<usize>> = HashMap::new();
+    let mut class_map: HashMap<usize, Vec<(CharClass, usize)>> = HashMap::new();
+    for (from, to_states) in position_automaton.follow.iter().enumerate() {
+      for &to in to_states {
+        let class = &position_automaton.pos_classes[to - 1];
+        match class.as_single() {
+          Some(chr) => transition_map.entry((from, chr)).or_default().push(to),
+          None => class_map.entry(from).or_default().push((class.clone(), to)),
+        }
+      }
+    }
+
+    NFAOne {
+      states_size: position_automaton.states_size(),
+      start: 0,
+      accept: position_automaton.accept.clone(),
+      transition_func: Box::new(move |state: usize, input: Option<char>| {
+        let chr = match input {
+          Some(chr) => chr,
+          None => return vec![],
+        };
+        let mut res = transition_map.get(&(state, chr)).cloned().unwrap_or_default();
+        for (class, to) in class_map.get(&state).into_iter().flatten() {
+          if class.contains(chr) {
+            res.push(*to);
+          }
+        }
+        res.sort_unstable();
+        res
       })
     }
   }
@@ -376,7 +731,7 @@ mod tests {
 
   #[test]
   fn regexp_number() {
-    let num_exp = DFAOne::from_regexp("((1|2|3|4|5|6|7|8|9)(0|1|2|3|4|5|6|7|8|9)*|0)(.(0|1|2|3|4|5|6|7|8|9)+)?", "0123456789.");
+    let num_exp = DFAOne::from_regexp("((1|2|3|4|5|6|7|8|9)(0|1|2|3|4|5|6|7|8|9)*|0)(\\.(0|1|2|3|4|5|6|7|8|9)+)?", "0123456789.");
     assert!(num_exp.test("0"));
     assert!(num_exp.test("4"));
     assert!(num_exp.test("10"));
@@ -392,4 +747,215 @@ mod tests {
     assert!(!num_exp.test("01.123"));
     assert!(!num_exp.test("01."));
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn regexp_states_size() {
+    // (a|b)*abb gives the four states of the textbook direct construction
+    let regexp = DFAOne::from_regexp("(a|b)*abb", "ab");
+    assert_eq!(regexp.states_size, 4);
+    assert_eq!(regexp.state_minimization("ab").states_size, 4);
+
+    // the empty position set is the dead state and is not counted
+    let regexp = DFAOne::from_regexp("abc", "abc");
+    assert_eq!(regexp.states_size, 4);
+    assert_eq!(regexp.transition(&regexp.init_state(), 'b'), None);
+    let min_regexp = regexp.state_minimization("abc");
+    assert_eq!(min_regexp.states_size, 4);
+    assert!(min_regexp.test("abc"));
+    assert!(!min_regexp.test("ab"));
+    assert!(!min_regexp.test("abca"));
+
+    let regexp = DFAOne::from_regexp("(a|bc)*abb", "abc");
+    let min_regexp = regexp.state_minimization("abc");
+    assert_eq!(min_regexp.states_size, 5);
+    assert!(min_regexp.test("abcbcabcaabb"));
+    assert!(!min_regexp.test("abcbcabbc"));
+  }
+
+  #[test]
+  fn regexp_intersect_complement() {
+    let ident = DFAOne::from_regexp("(a|e|f|h|i|l|s|w)+&~(if|else|while)", "aefhilsw");
+    assert!(ident.test("i"));
+    assert!(ident.test("iff"));
+    assert!(ident.test("elsewhile"));
+    assert!(ident.test("whil"));
+    assert!(!ident.test("if"));
+    assert!(!ident.test("else"));
+    assert!(!ident.test("while"));
+    assert!(!ident.test(""));
+
+    // ~ binds looser than postfix operators and tighter than concatenation
+    let no_bb = DFAOne::from_regexp("~((a|b)*bb(a|b)*)", "ab");
+    assert!(no_bb.test("abab"));
+    assert!(!no_bb.test("abba"));
+    let regexp = DFAOne::from_regexp("~a*b", "ab");
+    assert!(regexp.test("bab"));
+    assert!(!regexp.test("aab"));
+    assert!(!regexp.test("b"));
+    let regexp = DFAOne::from_regexp("a|b&b", "ab");
+    assert!(regexp.test("a"));
+    assert!(regexp.test("b"));
+  }
+
+  #[test]
+  fn glushkov_instance() {
+    let regexp = NFAOne::glushkov_from_regexp("(a|b)*abb");
+    assert_eq!(regexp.states_size, 6);
+    assert!(regexp.test("ababb"));
+    assert!(!regexp.test("abab"));
+    assert!(regexp.test("abb"));
+    assert!(!regexp.test("ab"));
+    for state in 0..regexp.states_size {
+      assert!((regexp.transition_func)(state, None).is_empty());
+      assert_eq!(regexp.e_closure(vec![state]), vec![state]);
+    }
+
+    let num_exp = NFAOne::glushkov_from_regexp("((1|2|3|4|5|6|7|8|9)(0|1|2|3|4|5|6|7|8|9)*|0)(\\.(0|1|2|3|4|5|6|7|8|9)+)?");
+    // + gets no copy of its operand, one state per written symbol plus the start
+    assert_eq!(num_exp.states_size, 32);
+    assert!(num_exp.test("0"));
+    assert!(num_exp.test("12.34"));
+    assert!(!num_exp.test("010"));
+    assert!(!num_exp.test("0."));
+
+    let nullable = NFAOne::glushkov_from_regexp("a*(b|c?)");
+    assert_eq!(nullable.states_size, 4);
+    assert!(nullable.test(""));
+    assert!(nullable.test("aac"));
+    assert!(!nullable.test("bc"));
+  }
+
+  #[test]
+  #[should_panic(expected = "backreference \\1 is not regular")]
+  fn regexp_rejects_backreference() {
+    DFAOne::from_regexp("(a|b)\\1", "ab");
+  }
+
+  #[test]
+  #[should_panic(expected = "^ and $ can't be combined with & and ~")]
+  fn regexp_rejects_anchor_in_intersection() {
+    DFAOne::from_regexp("^a&a", "a");
+  }
+
+  #[test]
+  #[should_panic(expected = "^ and $ are only supported by DFAOne, NFAOne and BacktrackOne")]
+  fn glushkov_rejects_anchor() {
+    NFAOne::glushkov_from_regexp("a$");
+  }
+
+  #[test]
+  #[should_panic(expected = "lookaround is not regular")]
+  fn regexp_rejects_lookaround() {
+    DFAOne::from_regexp("a(?=b)b", "ab");
+  }
+
+  #[test]
+  fn regexp_repeat() {
+    let dfa = DFAOne::from_regexp("(ab){2,3}c{2,}", "abc");
+    let nfa = NFAOne::from_regexp("(ab){2,3}c{2,}");
+    for (s, expected) in [("ababcc", true), ("abababccc", true), ("abcc", false), ("ababababcc", false), ("ababc", false)] {
+      assert_eq!(dfa.test(s), expected, "{}", s);
+      assert_eq!(nfa.test(s), expected, "{}", s);
+    }
+    assert!(NFAOne::from_regexp("a{0}b").test("b"));
+    assert!(NFAOne::from_regexp("(a|b){3}").test("bab"));
+    assert!(!DFAOne::from_regexp("(a|b){3}", "ab").test("ba"));
+  }
+
+  #[test]
+  fn reverse_ast() {
+    let reversed = |reg_exp: &str| parse_ast_regexp(reg_exp).reverse().to_string();
+    assert_eq!(reversed("abc"), "cba");
+    assert_eq!(reversed("(ab|cd)*e+"), "e+(ba|dc)*");
+    assert_eq!(reversed("~(ab)&a{2,}b?"), "~(ba)&b?a{2,}");
+    let dfa = DFAOne::reverse_from_regexp("(a|b)*abb", "ab");
+    assert!(dfa.test("bbaab"));
+    assert!(!dfa.test("abb"));
+    assert_eq!(reversed("^a(?m)\n^b$"), "(?m:^)b(?m:$)\na$");
+    let dfa = DFAOne::reverse_from_regexp("(?m)a$\nb", "ab\n");
+    assert!(dfa.test("b\na"));
+  }
+
+  #[test]
+  fn display() {
+    let print = |reg_exp: &str| parse_ast_regexp(reg_exp).to_string();
+    assert_eq!(print("((a)(b))|(c*)"), "ab|c*");
+    assert_eq!(print("a(b|c)"), "a(b|c)");
+    assert_eq!(print("a(bc)"), "a(bc)");
+    assert_eq!(print("(ab)*x+y?z{2,}"), "(ab)*x+y?z{2,}");
+    assert_eq!(print("~(a|b)&~a*"), "~(a|b)&~a*");
+    assert_eq!(print("(~a)*"), "(~a)*");
+    assert_eq!(print("\\p{Greek}\\PL\\(\\*()"), "\\p{Greek}\\PL\\(\\*()");
+    assert_eq!(print(""), "()");
+    assert_eq!(print("\\p{U+61-U+63,U+78}\\P{U+A}"), "\\p{U+61-U+63,U+78}\\P{U+A}");
+    assert_eq!(print("^.\\.(?s).$"), "^.\\.(?s:.)$");
+    assert_eq!(print("(?m)(^a$)|\\^\\$"), "(?m:^)a(?m:$)|\\^\\$");
+  }
+
+  #[test]
+  fn display_wide_class() {
+    let wide = |class: CharClass| RegASTNode::Binary {
+      node_type: NodeType::Concat,
+      left_child: Box::new(RegASTNode::Class(class)),
+      right_child: Box::new(RegASTNode::Endmarker),
+    };
+    let not_a = wide(CharClass::single('a').negate());
+    assert_eq!(not_a.to_string(), "\\P{U+61}");
+    let cjk = wide(CharClass::new(vec![('\u{4E00}', '\u{9FFF}'), ('\u{20000}', '\u{2A6DF}'), ('x', 'y')]));
+    assert_eq!(cjk.to_string(), "\\p{U+78-U+79,U+4E00-U+9FFF,U+20000-U+2A6DF}");
+    for ast in [not_a, cjk] {
+      assert_eq!(parse_ast_regexp(&ast.to_string()), ast);
+    }
+  }
+
+  // random trees from a fixed seed linear congruential generator
+  fn random_ast(seed: &mut u64, depth: usize) -> RegASTNode {
+    *seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+    let pick = (*seed >> 33) as usize;
+    let unary = |node_type, seed: &mut u64| RegASTNode::Unary {
+      node_type,
+      child: Box::new(random_ast(seed, depth - 1)),
+    };
+    let binary = |node_type, seed: &mut u64| RegASTNode::Binary {
+      node_type,
+      left_child: Box::new(random_ast(seed, depth - 1)),
+      right_child: Box::new(random_ast(seed, depth - 1)),
+    };
+    match if depth == 0 { pick % 4 } else { pick % 12 } {
+      0 => RegASTNode::LeafEmpty,
+      1 => RegASTNode::Leaf(['a', 'b', '(', '*', '\\', '{'][pick / 12 % 6]),
+      2 => match parse_ast_regexp("\\p{Greek}") {
+        RegASTNode::Binary { left_child, .. } => *left_child,
+        _ => unreachable!(),
+      },
+      3 => match pick / 12 % 3 {
+        0 => RegASTNode::Leaf('.'),
+        1 => RegASTNode::Anchor([Anchor::StartText, Anchor::StartLine, Anchor::EndLine, Anchor::EndText][pick / 36 % 4]),
+        _ => RegASTNode::Leaf('c'),
+      },
+      4 => unary(NodeType::Closure, seed),
+      5 => unary(NodeType::Plus, seed),
+      6 => unary(NodeType::Question, seed),
+      7 => unary(NodeType::Repeat { min: pick / 12 % 3, max: [None, Some(2), Some(3)][pick / 36 % 3] }, seed),
+      8 => unary(NodeType::Complement, seed),
+      9 => binary(NodeType::Union, seed),
+      10 => binary(NodeType::Intersect, seed),
+      _ => binary(NodeType::Concat, seed),
+    }
+  }
+
+  #[test]
+  fn display_round_trip() {
+    let mut seed = 42;
+    for _ in 0..2000 {
+      let ast = random_ast(&mut seed, 5);
+      let printed = ast.to_string();
+      let expected = RegASTNode::Binary {
+        node_type: NodeType::Concat,
+        left_child: Box::new(ast),
+        right_child: Box::new(RegASTNode::Endmarker),
+      };
+      assert_eq!(parse_ast_regexp(&printed), expected, "{}", printed);
+    }
+  }
+}
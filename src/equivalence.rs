@@ -0,0 +1,120 @@
+use super::automaton::Automaton;
+use std::collections::{HashSet, VecDeque};
+use std::hash::Hash;
+
+// breadth first search over the product automaton, returns the shortest input
+// on which `is_counterexample(a_accept, b_accept)` holds
+fn find_counterexample<A, B>(
+  a: &A,
+  b: &B,
+  input: &str,
+  is_counterexample: impl Fn(bool, bool) -> bool,
+) -> Result<(), String>
+where
+  A: Automaton,
+  B: Automaton,
+  A::State: Clone + Eq + Hash,
+  B::State: Clone + Eq + Hash,
+{
+  // every visited pair and the (parent, char) it was reached from
+  let mut nodes: Vec<(A::State, B::State)> = vec![];
+  let mut parents: Vec<Option<(usize, char)>> = vec![];
+  let mut visited: HashSet<(A::State, B::State)> = HashSet::new();
+  let mut queue = VecDeque::new();
+
+  let start = (a.init_state(), b.init_state());
+  visited.insert(start.clone());
+  nodes.push(start);
+  parents.push(None);
+  queue.push_back(0);
+
+  while let Some(idx) = queue.pop_front() {
+    let (ref sa, ref sb) = nodes[idx];
+    if is_counterexample(a.is_accept(sa), b.is_accept(sb)) {
+      let mut res = vec![];
+      let mut curr = idx;
+      while let Some((parent, chr)) = parents[curr] {
+        res.push(chr);
+        curr = parent;
+      }
+      return Err(res.into_iter().rev().collect());
+    }
+    if a.is_dead(sa) && b.is_dead(sb) {
+      continue;
+    }
+    let (sa, sb) = (sa.clone(), sb.clone());
+    for chr in input.chars() {
+      let next = (a.transition(&sa, chr), b.transition(&sb, chr));
+      if visited.insert(next.clone()) {
+        nodes.push(next);
+        parents.push(Some((idx, chr)));
+        queue.push_back(nodes.len() - 1);
+      }
+    }
+  }
+  Ok(())
+}
+
+/// Checks whether `a` and `b` accept the same language over `input`,
+/// otherwise returns a shortest string accepted by exactly one of them.
+pub fn equivalent<A, B>(a: &A, b: &B, input: &str) -> Result<(), String>
+where
+  A: Automaton,
+  B: Automaton,
+  A::State: Clone + Eq + Hash,
+  B::State: Clone + Eq + Hash,
+{
+  find_counterexample(a, b, input, |a_accept, b_accept| a_accept != b_accept)
+}
+
+/// Checks whether the language of `a` is included in the language of `b` over `input`,
+/// otherwise returns a shortest string accepted by `a` but not by `b`.
+pub fn is_subset<A, B>(a: &A, b: &B, input: &str) -> Result<(), String>
+where
+  A: Automaton,
+  B: Automaton,
+  A::State: Clone + Eq + Hash,
+  B::State: Clone + Eq + Hash,
+{
+  find_counterexample(a, b, input, |a_accept, b_accept| a_accept && !b_accept)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::dfa::DFAOne;
+  use super::super::nfa::NFAOne;
+
+  #[test]
+  fn equivalent_works() {
+    let dfa = DFAOne::from_regexp("(a|b)*abb", "ab");
+    let nfa = NFAOne::from_regexp("(a|b)*abb");
+    assert_eq!(equivalent(&dfa, &nfa, "ab"), Ok(()));
+    assert_eq!(equivalent(&nfa, &dfa.state_minimization("ab"), "ab"), Ok(()));
+
+    let star = NFAOne::from_regexp("(a|b)*");
+    let nested_star = NFAOne::from_regexp("(a*b*)*");
+    assert_eq!(equivalent(&star, &nested_star, "ab"), Ok(()));
+
+    let other = DFAOne::from_regexp("(a|b)*bab", "ab");
+    assert_eq!(equivalent(&dfa, &other, "ab"), Err("abb".to_string()));
+    assert_eq!(
+      equivalent(&NFAOne::from_regexp("a*"), &NFAOne::from_regexp("a+"), "a"),
+      Err("".to_string())
+    );
+  }
+
+  #[test]
+  fn is_subset_works() {
+    let plus = NFAOne::from_regexp("a+");
+    let star = DFAOne::from_regexp("a*", "a");
+    assert_eq!(is_subset(&plus, &star, "a"), Ok(()));
+    assert_eq!(is_subset(&star, &plus, "a"), Err("".to_string()));
+
+    let ab = DFAOne::from_regexp("(a|b)*", "ab");
+    let abb = NFAOne::from_regexp("(a|b)*abb");
+    assert_eq!(is_subset(&abb, &ab, "ab"), Ok(()));
+    assert_eq!(is_subset(&ab, &abb, "ab"), Err("".to_string()));
+    assert_eq!(is_subset(&NFAOne::from_regexp("aab|b"), &abb, "ab"), Err("b".to_string()));
+  }
+}
@@ -0,0 +1,256 @@
+use super::char_class::CharClass;
+use std::sync::OnceLock;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Flags {
+  pub case_insensitive: bool, // i
+  pub verbose: bool, // x, whitespace and # comments are ignored
+  pub multi_line: bool, // m, ^ and $ also match next to a \n
+  pub dot_matches_new_line: bool, // s
+}
+
+impl Flags {
+  pub fn case_insensitive(self, on: bool) -> Self {
+    Flags { case_insensitive: on, ..self }
+  }
+
+  pub fn verbose(self, on: bool) -> Self {
+    Flags { verbose: on, ..self }
+  }
+
+  pub fn multi_line(self, on: bool) -> Self {
+    Flags { multi_line: on, ..self }
+  }
+
+  pub fn dot_matches_new_line(self, on: bool) -> Self {
+    Flags { dot_matches_new_line: on, ..self }
+  }
+
+  // None for a letter that isn't a flag
+  pub fn set(self, flag: char, on: bool) -> Option<Self> {
+    match flag {
+      'i' => Some(self.case_insensitive(on)),
+      'x' => Some(self.verbose(on)),
+      'm' => Some(self.multi_line(on)),
+      's' => Some(self.dot_matches_new_line(on)),
+      _ => None,
+    }
+  }
+}
+
+// pairs that simple case folding puts in the same orbit but that `to_lowercase`
+// and `to_uppercase` don't connect
+const EXTRA_FOLDS: &[(char, char)] = &[
+  ('\u{212A}', 'k'), // KELVIN SIGN
+  ('\u{212B}', '\u{E5}'), // ANGSTROM SIGN
+  ('\u{17F}', 's'), // LATIN SMALL LETTER LONG S
+  ('\u{1E9E}', '\u{DF}'), // LATIN CAPITAL LETTER SHARP S
+  ('\u{B5}', '\u{3BC}'), // MICRO SIGN
+  ('\u{345}', '\u{3B9}'), // COMBINING GREEK YPOGEGRAMMENI
+  ('\u{1FBE}', '\u{3B9}'), // GREEK PROSGEGRAMMENI
+  ('\u{3C2}', '\u{3C3}'), // GREEK SMALL LETTER FINAL SIGMA
+  ('\u{3D0}', '\u{3B2}'), // GREEK BETA SYMBOL
+  ('\u{3D1}', '\u{3B8}'), // GREEK THETA SYMBOL
+  ('\u{3D5}', '\u{3C6}'), // GREEK PHI SYMBOL
+  ('\u{3D6}', '\u{3C0}'), // GREEK PI SYMBOL
+  ('\u{3F0}', '\u{3BA}'), // GREEK KAPPA SYMBOL
+  ('\u{3F1}', '\u{3C1}'), // GREEK RHO SYMBOL
+  ('\u{3F5}', '\u{3B5}'), // GREEK LUNATE EPSILON SYMBOL
+];
+
+fn single(mut chars: impl Iterator<Item = char>) -> Option<char> {
+  match (chars.next(), chars.next()) {
+    (Some(chr), None) => Some(chr),
+    _ => None,
+  }
+}
+
+// every char equal to `chr` under simple case folding, `chr` included, sorted
+pub fn case_variants(chr: char) -> Vec<char> {
+  let mut res = vec![chr];
+  let mut idx = 0;
+  while idx < res.len() {
+    let curr = res[idx];
+    let mut related: Vec<char> = single(curr.to_lowercase())
+      .into_iter()
+      .chain(single(curr.to_uppercase()))
+      .collect();
+    for &(a, b) in EXTRA_FOLDS {
+      if a == curr {
+        related.push(b);
+      } else if b == curr {
+        related.push(a);
+      }
+    }
+    for r in related {
+      if !res.contains(&r) {
+        res.push(r);
+      }
+    }
+    idx += 1;
+  }
+  res.sort_unstable();
+  res
+}
+
+// every char with case variants other than itself, with its variants
+fn cased_chars() -> &'static [(char, Vec<char>)] {
+  static CASED: OnceLock<Vec<(char, Vec<char>)>> = OnceLock::new();
+  CASED.get_or_init(|| {
+    let maybe_cased = |chr: char| {
+      single(chr.to_lowercase()) != Some(chr)
+        || single(chr.to_uppercase()) != Some(chr)
+        || EXTRA_FOLDS.iter().any(|&(a, b)| a == chr || b == chr)
+    };
+    (0..=char::MAX as u32)
+      .filter_map(char::from_u32)
+      .filter(|&chr| maybe_cased(chr))
+      .map(|chr| (chr, case_variants(chr)))
+      .filter(|(_, variants)| variants.len() > 1)
+      .collect()
+  })
+}
+
+// `class` with the case variants of its chars added. A named class that grows is
+// renamed to the flag group it was folded by, so it prints back the same.
+pub fn case_fold(class: &CharClass) -> CharClass {
+  let mut ranges = class.ranges().to_vec();
+  for (chr, variants) in cased_chars() {
+    if class.contains(*chr) {
+      ranges.extend(variants.iter().map(|&variant| (variant, variant)));
+    }
+  }
+  let folded = CharClass::new(ranges);
+  match class.name() {
+    _ if folded == CharClass::new(class.ranges().to_vec()) => class.clone(),
+    Some(name) => folded.with_name(format!("(?i:{})", name)),
+    None => folded,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::automaton::Automaton;
+  use super::super::backtrack::BacktrackOne;
+  use super::super::dfa::DFAOne;
+  use super::super::dfa_regexp::parse_ast_regexp;
+  use super::super::nfa::NFAOne;
+
+  #[test]
+  fn case_variants_works() {
+    assert_eq!(case_variants('a'), vec!['A', 'a']);
+    assert_eq!(case_variants('1'), vec!['1']);
+    assert_eq!(case_variants('k'), vec!['K', 'k', '\u{212A}']);
+    assert_eq!(case_variants('\u{3C3}'), vec!['\u{3A3}', '\u{3C2}', '\u{3C3}']);
+    // ß uppercases to "SS", only the capital sharp s is a simple fold
+    assert_eq!(case_variants('\u{DF}'), vec!['\u{DF}', '\u{1E9E}']);
+  }
+
+  #[test]
+  fn case_fold_works() {
+    let upper = case_fold(&CharClass::from_property("Lu").unwrap());
+    assert!(upper.contains('a') && upper.contains('\u{3C2}') && upper.contains('\u{212A}'));
+    assert!(!upper.contains('1'));
+    let digits = CharClass::new(vec![('0', '9')]);
+    assert_eq!(case_fold(&digits), digits);
+    assert_eq!(case_fold(&CharClass::new(vec![('a', 'c')])).ranges(), &[('A', 'C'), ('a', 'c')]);
+    // the folded class prints as the flag group
+    let ast = parse_ast_regexp("(?i)\\p{Lu}x");
+    assert_eq!(ast.to_string(), "(?i:\\p{Lu})\\p{U+58,U+78}");
+    assert_eq!(parse_ast_regexp(&ast.to_string()), ast);
+  }
+
+  #[test]
+  fn inline_flags() {
+    let keyword = NFAOne::from_regexp("(?i)while|(?-i)for");
+    assert!(keyword.test("WhIlE"));
+    assert!(keyword.test("for"));
+    assert!(!keyword.test("For"));
+
+    let dfa = DFAOne::from_regexp("(?i:ab)c", "abcABC");
+    assert!(dfa.test("aBc"));
+    assert!(!dfa.test("abC"));
+
+    let verbose = NFAOne::from_regexp("(?x)
+      (1|2)+   # integer part
+      (\\.(1|2)+)? # fraction
+    ");
+    assert!(verbose.test("12.21"));
+    assert!(!verbose.test("12 .21"));
+
+    // classes fold like single chars
+    for nfa in [NFAOne::from_regexp("(?i)\\p{U+61-U+63}"), NFAOne::from_regexp("(?i:\\p{Ll})")] {
+      assert!(nfa.test("B"));
+      assert!(!nfa.test("1"));
+    }
+    let dfa = DFAOne::from_regexp("(?i)\\P{Lu}", "aA1");
+    assert!(dfa.test("A"));
+    assert!(dfa.test("1"));
+  }
+
+  #[test]
+  fn builder_flags() {
+    let flags = Flags::default().case_insensitive(true).verbose(true);
+    let nfa = NFAOne::from_regexp_with_flags("select | from", flags);
+    assert!(nfa.test("SELECT"));
+    assert!(nfa.test("From"));
+    assert!(!nfa.test("select from"));
+    let dfa = DFAOne::from_regexp_with_flags("select | (?-i)from", "selctfromSELCTFROM", flags);
+    assert!(dfa.test("sElEcT"));
+    assert!(!dfa.test("From"));
+    // the builder flags are applied once, so an inline group can still turn them off
+    let nfa = NFAOne::from_regexp_with_flags("a (?-x:b c)", Flags::default().verbose(true));
+    assert!(nfa.test("ab c"));
+    assert!(!nfa.test("abc"));
+  }
+
+  #[test]
+  fn escapes_kept_next_to_flags() {
+    let nfa = NFAOne::from_regexp("(?i)a{2}\\}\\p{Greek}\\{");
+    assert!(nfa.test("aA}\u{3A9}{"));
+    assert!(!nfa.test("aA\\}\u{3A9}{"));
+  }
+
+  #[test]
+  #[should_panic(expected = "parse error at 5: unmatched )")]
+  fn unmatched_paren_after_flags() {
+    NFAOne::from_regexp("(?i)a)b");
+  }
+
+  #[test]
+  fn multi_line() {
+    // one a or b per line
+    let lines = "(^(a|b)$\n?)*";
+    let nfa = NFAOne::from_regexp(lines);
+    let multi_line_nfa = NFAOne::from_regexp_with_flags(lines, Flags::default().multi_line(true));
+    let dfa = DFAOne::from_regexp(lines, "ab\n");
+    let multi_line_dfa = DFAOne::from_regexp_with_flags(lines, "ab\n", Flags::default().multi_line(true));
+    let backtrack = BacktrackOne::from_regexp(&format!("(?m){}", lines));
+    for (s, expected) in [("a", true), ("a\nb", true), ("a\nb\n", true), ("ab", false), ("a\n\nb", false)] {
+      assert_eq!(multi_line_nfa.test(s), expected, "{:?}", s);
+      assert_eq!(multi_line_dfa.test(s), expected, "{:?}", s);
+      assert_eq!(backtrack.test(s), expected, "{:?}", s);
+      // without m only the first line can start and the last one end
+      assert_eq!(nfa.test(s), s.len() == 1, "{:?}", s);
+      assert_eq!(dfa.test(s), s.len() == 1, "{:?}", s);
+    }
+    assert!(!NFAOne::from_regexp("(?m)a$b").test("ab"));
+    assert!(!DFAOne::from_regexp("a$\n", "a\n").test("a\n"));
+    assert!(DFAOne::from_regexp("(?m)a$\n", "a\n").test("a\n"));
+  }
+
+  #[test]
+  fn dot_matches_new_line() {
+    let nfa = NFAOne::from_regexp("a.c");
+    assert!(nfa.test("abc") && nfa.test("a.c"));
+    assert!(!nfa.test("a\nc"));
+    assert!(NFAOne::from_regexp("(?s)a.c").test("a\nc"));
+    assert!(NFAOne::from_regexp("a\\.c").test("a.c"));
+    assert!(!NFAOne::from_regexp("a\\.c").test("abc"));
+    let dfa = DFAOne::from_regexp_with_flags("a.c", "abc\n", Flags::default().dot_matches_new_line(true));
+    assert!(dfa.test("a\nc"));
+    assert!(!DFAOne::from_regexp("a(?s:.)c.", "abc\n").test("a\nc\n"));
+    assert!(BacktrackOne::from_regexp("(?s:.)(.)\\1").test("\nbb"));
+  }
+}
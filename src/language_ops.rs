@@ -0,0 +1,412 @@
+use super::automaton::Automaton;
+use super::convert_nfa_to_dfa::convert_nfa_to_dfa;
+use super::dfa::DFAOne;
+use super::nfa::NFAOne;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+fn empty_dfa() -> DFAOne {
+  DFAOne::new(0, None, vec![], Box::new(|_, _| None), "")
+}
+
+// explore every state reachable from `init`, states with `is_dead` are left as None
+pub fn build_dfa<S: Clone + Eq + Hash>(
+  init: S,
+  step: impl Fn(&S, char) -> S,
+  is_dead: impl Fn(&S) -> bool,
+  is_accept: impl Fn(&S) -> bool,
+  input: &str,
+) -> DFAOne {
+  if is_dead(&init) {
+    return empty_dfa();
+  }
+  let mut states: Vec<S> = vec![init.clone()];
+  let mut states_idx_map: HashMap<S, usize> = HashMap::new();
+  let mut transition_map: HashMap<(usize, char), usize> = HashMap::new();
+  states_idx_map.insert(init, 0);
+
+  let mut curr_idx = 0;
+  while curr_idx < states.len() {
+    for chr in input.chars() {
+      let new_state = step(&states[curr_idx], chr);
+      if is_dead(&new_state) {
+        continue;
+      }
+      let new_idx = match states_idx_map.get(&new_state) {
+        Some(&idx) => idx,
+        None => {
+          states.push(new_state.clone());
+          states_idx_map.insert(new_state, states.len() - 1);
+          states.len() - 1
+        },
+      };
+      transition_map.insert((curr_idx, chr), new_idx);
+    }
+    curr_idx += 1;
+  }
+
+  DFAOne::new(
+    states.len(),
+    Some(0),
+    (0..states.len()).filter(|&idx| is_accept(&states[idx])).collect(),
+    Box::new(move |s: usize, chr: char| {
+      transition_map.get(&(s, chr)).copied()
+    }),
+    input,
+  )
+}
+
+pub fn determinize<A>(a: &A, input: &str) -> DFAOne
+where
+  A: Automaton,
+  A::State: Clone + Eq + Hash,
+{
+  build_dfa(
+    a.init_state(),
+    |s, chr| a.transition(s, chr),
+    |s| a.is_dead(s),
+    |s| a.is_accept(s),
+    input,
+  )
+}
+
+type NFATransitionMap = HashMap<(usize, Option<char>), Vec<usize>>;
+
+// copy the edges of `dfa` into an NFA transition map, shifting its states by `offset`
+fn add_dfa_edges(transition_map: &mut NFATransitionMap, dfa: &DFAOne, offset: usize, input: &str) {
+  for s in 0..dfa.states_size {
+    for chr in input.chars() {
+      if let Some(t) = (dfa.transition_func)(s, chr) {
+        transition_map.entry((s + offset, Some(chr))).or_default().push(t + offset);
+      }
+    }
+  }
+}
+
+fn nfa_from_map(states_size: usize, start: usize, accept: Vec<usize>, transition_map: NFATransitionMap) -> NFAOne {
+  NFAOne {
+    states_size,
+    start,
+    accept,
+    transition_func: Box::new(move |s: usize, chr: Option<char>| {
+      transition_map.get(&(s, chr)).cloned().unwrap_or_default()
+    }),
+  }
+}
+
+fn product<A, B>(a: &A, b: &B, input: &str, accept_op: impl Fn(bool, bool) -> bool) -> DFAOne
+where
+  A: Automaton,
+  B: Automaton,
+  A::State: Clone + Eq + Hash,
+  B::State: Clone + Eq + Hash,
+{
+  let dead_accepts = accept_op(false, false);
+  build_dfa(
+    (a.init_state(), b.init_state()),
+    |(sa, sb), chr| (a.transition(sa, chr), b.transition(sb, chr)),
+    |(sa, sb)| !dead_accepts && a.is_dead(sa) && b.is_dead(sb),
+    |(sa, sb)| accept_op(a.is_accept(sa), b.is_accept(sb)),
+    input,
+  ).state_minimization(input)
+}
+
+pub fn union<A, B>(a: &A, b: &B, input: &str) -> DFAOne
+where
+  A: Automaton,
+  B: Automaton,
+  A::State: Clone + Eq + Hash,
+  B::State: Clone + Eq + Hash,
+{
+  product(a, b, input, |a_accept, b_accept| a_accept || b_accept)
+}
+
+pub fn intersection<A, B>(a: &A, b: &B, input: &str) -> DFAOne
+where
+  A: Automaton,
+  B: Automaton,
+  A::State: Clone + Eq + Hash,
+  B::State: Clone + Eq + Hash,
+{
+  product(a, b, input, |a_accept, b_accept| a_accept && b_accept)
+}
+
+pub fn difference<A, B>(a: &A, b: &B, input: &str) -> DFAOne
+where
+  A: Automaton,
+  B: Automaton,
+  A::State: Clone + Eq + Hash,
+  B::State: Clone + Eq + Hash,
+{
+  product(a, b, input, |a_accept, b_accept| a_accept && !b_accept)
+}
+
+pub fn symmetric_difference<A, B>(a: &A, b: &B, input: &str) -> DFAOne
+where
+  A: Automaton,
+  B: Automaton,
+  A::State: Clone + Eq + Hash,
+  B::State: Clone + Eq + Hash,
+{
+  product(a, b, input, |a_accept, b_accept| a_accept != b_accept)
+}
+
+pub fn concat<A, B>(a: &A, b: &B, input: &str) -> DFAOne
+where
+  A: Automaton,
+  B: Automaton,
+  A::State: Clone + Eq + Hash,
+  B::State: Clone + Eq + Hash,
+{
+  let (dfa_a, dfa_b) = (determinize(a, input), determinize(b, input));
+  let (start_a, start_b) = match (dfa_a.start, dfa_b.start) {
+    (Some(start_a), Some(start_b)) => (start_a, start_b),
+    _ => return empty_dfa(), // one side is empty, so is the result
+  };
+  // states of `b` follow the states of `a`, accept states of `a` go to the start of `b` by e
+  let offset = dfa_a.states_size;
+  let mut transition_map: NFATransitionMap = HashMap::new();
+  add_dfa_edges(&mut transition_map, &dfa_a, 0, input);
+  add_dfa_edges(&mut transition_map, &dfa_b, offset, input);
+  for &s in &dfa_a.accept {
+    transition_map.entry((s, None)).or_default().push(start_b + offset);
+  }
+  let nfa = nfa_from_map(
+    dfa_a.states_size + dfa_b.states_size,
+    start_a,
+    dfa_b.accept.iter().map(|&s| s + offset).collect(),
+    transition_map,
+  );
+  convert_nfa_to_dfa(nfa, input).state_minimization(input)
+}
+
+// kleene star
+pub fn closure<A>(a: &A, input: &str) -> DFAOne
+where
+  A: Automaton,
+  A::State: Clone + Eq + Hash,
+{
+  let dfa = determinize(a, input);
+  // the extra state `states_size` is both start and accept, it goes to the old start by e
+  // and every old accept state goes back to it by e
+  let new_start = dfa.states_size;
+  let mut transition_map: NFATransitionMap = HashMap::new();
+  add_dfa_edges(&mut transition_map, &dfa, 0, input);
+  transition_map.insert((new_start, None), dfa.start.into_iter().collect());
+  for &s in &dfa.accept {
+    transition_map.entry((s, None)).or_default().push(new_start);
+  }
+  let nfa = nfa_from_map(dfa.states_size + 1, new_start, vec![new_start], transition_map);
+  convert_nfa_to_dfa(nfa, input).state_minimization(input)
+}
+
+// complement relative to the strings over `input`
+pub fn complement<A>(a: &A, input: &str) -> DFAOne
+where
+  A: Automaton,
+  A::State: Clone + Eq + Hash,
+{
+  build_dfa(
+    a.init_state(),
+    |s, chr| a.transition(s, chr),
+    |_| false, // the dead state of `a` becomes an accepting one
+    |s| !a.is_accept(s),
+    input,
+  ).state_minimization(input)
+}
+
+pub fn reverse<A>(a: &A, input: &str) -> DFAOne
+where
+  A: Automaton,
+  A::State: Clone + Eq + Hash,
+{
+  let dfa = determinize(a, input);
+  let transition_func = &dfa.transition_func;
+  let edges = (0..dfa.states_size).flat_map(|s| {
+    input.chars().filter_map(move |chr| transition_func(s, chr).map(|t| (s, Some(chr), t)))
+  });
+  let nfa = reverse_edges(dfa.states_size, dfa.start.into_iter().collect(), &dfa.accept, edges);
+  convert_nfa_to_dfa(nfa, input).state_minimization(input)
+}
+
+// every edge turned around, the extra state `states_size` is the new start and goes
+// to the old accept states by e, the old start states become the accept states
+fn reverse_edges(
+  states_size: usize,
+  start: Vec<usize>,
+  accept: &[usize],
+  edges: impl Iterator<Item = (usize, Option<char>, usize)>,
+) -> NFAOne {
+  let new_start = states_size;
+  let mut transition_map: NFATransitionMap = HashMap::new();
+  for (s, by, t) in edges {
+    transition_map.entry((t, by)).or_default().push(s);
+  }
+  transition_map.insert((new_start, None), accept.to_vec());
+  nfa_from_map(states_size + 1, new_start, start, transition_map)
+}
+
+// start of the leftmost match ending at byte `end` of `haystack`, found by running
+// `reverse`, an automaton of the reversed language, from `end` towards the start
+pub fn match_start<A: Automaton>(reverse: &A, haystack: &str, end: usize) -> Option<usize> {
+  let mut res = None;
+  let mut state = reverse.init_state();
+  let mut chars = haystack[..end].char_indices().rev();
+  let mut pos = end;
+  loop {
+    if reverse.is_accept(&state) {
+      res = Some(pos);
+    }
+    if !reverse.is_match_state_reachable(&state) {
+      return res;
+    }
+    match chars.next() {
+      Some((idx, chr)) => {
+        state = reverse.transition(&state, chr);
+        pos = idx;
+      },
+      None => return res,
+    }
+  }
+}
+
+impl NFAOne {
+  // the reversed language over the chars of `input`
+  pub fn reverse(&self, input: &str) -> NFAOne {
+    let edges = (0..self.states_size).flat_map(|s| {
+      std::iter::once(None)
+        .chain(input.chars().map(Some))
+        .flat_map(move |by| (self.transition_func)(s, by).into_iter().map(move |t| (s, by, t)))
+    });
+    reverse_edges(self.states_size, vec![self.start], &self.accept, edges)
+  }
+}
+
+// all prefixes of strings in the language
+pub fn prefix_closure<A>(a: &A, input: &str) -> DFAOne
+where
+  A: Automaton,
+  A::State: Clone + Eq + Hash,
+{
+  // minimization merges every state that can't reach an accept state into the dead state,
+  // so each state left is a prefix of some accepted string
+  let dfa = determinize(a, input).state_minimization(input);
+  DFAOne {
+    accept: (0..dfa.states_size).collect(),
+    ..dfa
+  }
+}
+
+// all suffixes of strings in the language
+pub fn suffix_closure<A>(a: &A, input: &str) -> DFAOne
+where
+  A: Automaton,
+  A::State: Clone + Eq + Hash,
+{
+  reverse(&prefix_closure(&reverse(a, input), input), input)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::equivalence::equivalent;
+
+  #[test]
+  fn identifier_but_not_keyword() {
+    let ident = DFAOne::from_regexp("(a|b|f|i)(a|b|f|i)*", "abfi");
+    let keyword = NFAOne::from_regexp("if|fi");
+    let not_keyword = difference(&ident, &keyword, "abfi");
+    assert!(not_keyword.test("i"));
+    assert!(not_keyword.test("iff"));
+    assert!(not_keyword.test("ab"));
+    assert!(!not_keyword.test("if"));
+    assert!(!not_keyword.test("fi"));
+    assert!(!not_keyword.test(""));
+
+    let only_keyword = intersection(&ident, &keyword, "abfi");
+    assert_eq!(equivalent(&only_keyword, &keyword, "abfi"), Ok(()));
+    assert_eq!(only_keyword.states_size, 4);
+  }
+
+  #[test]
+  fn union_and_symmetric_difference() {
+    let a_star = NFAOne::from_regexp("a*");
+    let b_star = DFAOne::from_regexp("b*", "b");
+    let either = union(&a_star, &b_star, "ab");
+    assert_eq!(equivalent(&either, &NFAOne::from_regexp("a*|b*"), "ab"), Ok(()));
+
+    let sym = symmetric_difference(&a_star, &NFAOne::from_regexp("aa*"), "a");
+    assert!(sym.test(""));
+    assert!(!sym.test("a"));
+    assert!(!sym.test("aaa"));
+  }
+
+  #[test]
+  fn complement_works() {
+    let abb = NFAOne::from_regexp("(a|b)*abb");
+    let not_abb = complement(&abb, "ab");
+    assert!(not_abb.test(""));
+    assert!(not_abb.test("ab"));
+    assert!(not_abb.test("abba"));
+    assert!(!not_abb.test("abb"));
+    assert!(!not_abb.test("babb"));
+    // characters outside of the alphabet are rejected
+    assert!(!not_abb.test("c"));
+    assert_eq!(equivalent(&complement(&not_abb, "ab"), &abb, "ab"), Ok(()));
+    assert_eq!(complement(&NFAOne::from_regexp("(a|b)*"), "ab").start, None);
+  }
+
+  #[test]
+  fn concat_and_closure() {
+    let ab = DFAOne::from_regexp("a|b", "ab");
+    let abb = NFAOne::from_regexp("abb");
+    let ab_star = closure(&ab, "ab");
+    assert_eq!(equivalent(&ab_star, &NFAOne::from_regexp("(a|b)*"), "ab"), Ok(()));
+    let res = concat(&ab_star, &abb, "ab");
+    assert_eq!(equivalent(&res, &NFAOne::from_regexp("(a|b)*abb"), "ab"), Ok(()));
+    assert_eq!(res.states_size, 4);
+
+    let empty = intersection(&ab, &abb, "ab");
+    assert_eq!(concat(&ab, &empty, "ab").start, None);
+    assert!(closure(&empty, "ab").test(""));
+  }
+
+  #[test]
+  fn reverse_works() {
+    let abb = DFAOne::from_regexp("(a|b)*abb", "ab");
+    let bba = reverse(&abb, "ab");
+    assert_eq!(equivalent(&bba, &NFAOne::from_regexp("bba(a|b)*"), "ab"), Ok(()));
+    assert_eq!(equivalent(&reverse(&bba, "ab"), &abb, "ab"), Ok(()));
+  }
+
+  #[test]
+  fn reverse_nfa() {
+    let nfa = NFAOne::from_regexp("a(b|cd)*e?");
+    let reversed = nfa.reverse("abcde");
+    assert_eq!(equivalent(&reversed, &NFAOne::from_regexp("e?(b|dc)*a"), "abcde"), Ok(()));
+    assert_eq!(equivalent(&reversed.reverse("abcde"), &nfa, "abcde"), Ok(()));
+  }
+
+  #[test]
+  fn match_start_works() {
+    // the forward DFA finds where the first match ends, the reverse one where it starts
+    let forward = DFAOne::from_regexp("(a|b|x)*a+b", "abx");
+    let reverse = DFAOne::reverse_from_regexp("a+b", "abx");
+    let haystack = "xbaaabxab";
+    let end = forward.shortest_prefix_match(haystack).unwrap();
+    assert_eq!(end, 6);
+    assert_eq!(match_start(&reverse, haystack, end), Some(2));
+    assert_eq!(match_start(&reverse, haystack, 9), Some(7));
+    assert_eq!(match_start(&reverse, haystack, 5), None);
+    assert_eq!(match_start(&DFAOne::reverse_from_regexp("a*", "a"), "baa", 3), Some(1));
+  }
+
+  #[test]
+  fn prefix_and_suffix_closure() {
+    let abc = NFAOne::from_regexp("abc|bd");
+    let prefix = prefix_closure(&abc, "abcd");
+    assert_eq!(equivalent(&prefix, &NFAOne::from_regexp("(a|ab|abc|b|bd)?"), "abcd"), Ok(()));
+    let suffix = suffix_closure(&abc, "abcd");
+    assert_eq!(equivalent(&suffix, &NFAOne::from_regexp("(c|bc|abc|d|bd)?"), "abcd"), Ok(()));
+  }
+}
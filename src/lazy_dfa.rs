@@ -0,0 +1,194 @@
+use super::automaton::Automaton;
+use super::nfa::{NFAOne, NFAState};
+use super::regexp_parser;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+// how many times the cache may be cleared during one `test` before giving up on it
+const MAX_CACHE_CLEARS: usize = 8;
+
+struct LazyCache {
+  states: Vec<NFAState>,
+  states_idx_map: HashMap<NFAState, usize>,
+  transition_map: HashMap<(usize, char), usize>,
+  clears: usize,
+}
+
+impl LazyCache {
+  fn new() -> Self {
+    LazyCache {
+      states: vec![],
+      states_idx_map: HashMap::new(),
+      transition_map: HashMap::new(),
+      clears: 0,
+    }
+  }
+
+  fn clear(&mut self) {
+    self.states.clear();
+    self.states_idx_map.clear();
+    self.transition_map.clear();
+    self.clears += 1;
+  }
+}
+
+// subset construction done on demand: a DFA state is only computed when the input walks
+// into it, and at most `cache_capacity` of them are kept at once
+pub struct LazyDFA {
+  nfa: NFAOne,
+  cache_capacity: usize,
+  cache: RefCell<LazyCache>,
+  co_reachable: Option<Vec<bool>>, // per NFA state, when the alphabet is known
+}
+
+impl LazyDFA {
+  pub fn new(nfa: NFAOne, cache_capacity: usize) -> Self {
+    assert!(cache_capacity >= 2, "cache must hold at least two states");
+    LazyDFA {
+      nfa,
+      cache_capacity,
+      cache: RefCell::new(LazyCache::new()),
+      co_reachable: None,
+    }
+  }
+
+  pub fn from_regexp(reg_exp: &str) -> Self {
+    let nfa = NFAOne::from_regexp(reg_exp);
+    let co_reachable = nfa.co_reachable(&regexp_parser::parse(reg_exp).representative_chars());
+    LazyDFA {
+      co_reachable: Some(co_reachable),
+      ..LazyDFA::new(nfa, 1024)
+    }
+  }
+
+  pub fn cached_states_size(&self) -> usize {
+    self.cache.borrow().states.len()
+  }
+
+  pub fn cache_clears(&self) -> usize {
+    self.cache.borrow().clears
+  }
+
+  fn add_state(&self, cache: &mut LazyCache, state: NFAState) -> usize {
+    if let Some(&idx) = cache.states_idx_map.get(&state) {
+      return idx;
+    }
+    if cache.states.len() >= self.cache_capacity {
+      cache.clear();
+    }
+    let idx = cache.states.len();
+    cache.states.push(state.clone());
+    cache.states_idx_map.insert(state, idx);
+    idx
+  }
+
+  fn next_state(&self, cache: &mut LazyCache, curr: usize, chr: char) -> usize {
+    if let Some(&idx) = cache.transition_map.get(&(curr, chr)) {
+      return idx;
+    }
+    let new_state = self.nfa.e_closure(self.nfa.transition(&cache.states[curr], chr));
+    let clears = cache.clears;
+    let idx = self.add_state(cache, new_state);
+    if cache.clears == clears { // `curr` is gone if the cache was just cleared
+      cache.transition_map.insert((curr, chr), idx);
+    }
+    idx
+  }
+}
+
+impl Automaton for LazyDFA {
+  type State = NFAState;
+
+  fn init_state(&self) -> Self::State {
+    self.nfa.e_closure(vec![self.nfa.start])
+  }
+  fn is_dead(&self, s: &Self::State) -> bool {
+    s.is_empty()
+  }
+  fn is_accept(&self, s: &Self::State) -> bool {
+    self.nfa.is_accept(s)
+  }
+  fn is_match_state_reachable(&self, s: &Self::State) -> bool {
+    match &self.co_reachable {
+      Some(co_reachable) => s.iter().any(|&state| co_reachable[state]),
+      None => !s.is_empty(),
+    }
+  }
+  fn transition(&self, s: &Self::State, chr: char) -> Self::State {
+    let mut cache = self.cache.borrow_mut();
+    let curr = self.add_state(&mut cache, s.clone());
+    let next = self.next_state(&mut cache, curr, chr);
+    cache.states[next].clone()
+  }
+
+  fn test(&self, s: &str) -> bool {
+    let mut cache = self.cache.borrow_mut();
+    let clears_before = cache.clears;
+    let mut curr = self.add_state(&mut cache, self.init_state());
+    for chr in s.chars() {
+      if cache.states[curr].is_empty() {
+        return false;
+      }
+      curr = self.next_state(&mut cache, curr, chr);
+      if cache.clears - clears_before > MAX_CACHE_CLEARS {
+        // the cache is thrashing, simulating the NFA directly is cheaper
+        return self.nfa.test(s);
+      }
+    }
+    self.nfa.is_accept(&cache.states[curr])
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // (a|b)*a(a|b){n}, its DFA has 2^(n+1) states
+  fn nth_from_last_is_a(n: usize) -> String {
+    format!("(a|b)*a{}", "(a|b)".repeat(n))
+  }
+
+  #[test]
+  fn regexp_instance_1() {
+    let regexp = LazyDFA::from_regexp("(a|bc)*abb");
+    assert!(regexp.test("abcabb"));
+    assert!(regexp.test("aabb"));
+    assert!(!regexp.test("abcbcabcaabbc"));
+    assert!(!regexp.test("abcbcabbc"));
+    assert!(!regexp.test("abcdabb"));
+    assert_eq!(regexp.cache_clears(), 0);
+  }
+
+  #[test]
+  fn only_visited_states_are_built() {
+    let regexp = LazyDFA::from_regexp(&nth_from_last_is_a(20));
+    let input = format!("a{}", "b".repeat(20));
+    assert!(regexp.test(&input));
+    assert!(!regexp.test(&input[1..]));
+    assert!(regexp.cached_states_size() < 50);
+  }
+
+  #[test]
+  fn falls_back_when_cache_thrashes() {
+    let regexp = LazyDFA::new(NFAOne::from_regexp(&nth_from_last_is_a(6)), 4);
+    let input = "abaabbbaaababbbaab".repeat(4);
+    assert!(regexp.test(&input));
+    assert!(!regexp.test(&format!("{}bbbbbbb", input)));
+    assert!(regexp.test(&format!("{}abbbbbb", input)));
+    assert!(regexp.cache_clears() > MAX_CACHE_CLEARS);
+    assert!(regexp.cached_states_size() <= 4);
+  }
+
+  #[test]
+  fn transition_matches_nfa() {
+    let nfa = NFAOne::from_regexp("(a|b)*abb");
+    let regexp = LazyDFA::new(NFAOne::from_regexp("(a|b)*abb"), 2);
+    let (mut s, mut t) = (nfa.init_state(), regexp.init_state());
+    for chr in "babbaabb".chars() {
+      s = Automaton::transition(&nfa, &s, chr);
+      t = regexp.transition(&t, chr);
+      assert_eq!(s, t);
+    }
+    assert!(regexp.is_accept(&t));
+  }
+}
@@ -1,19 +1,23 @@
 use super::automaton::Automaton;
 use std::iter::Iterator;
 
-struct Lexer<A: Automaton, Lexeme>(Vec<(A, Box<dyn Fn(&str) -> Lexeme>)>);
+// builds the token out of the matched lexeme
+pub type Action<Lexeme> = Box<dyn Fn(&str) -> Lexeme>;
+
+// rules in priority order, each an automaton for its lexemes and its action
+pub struct Lexer<A: Automaton, Lexeme>(pub Vec<(A, Action<Lexeme>)>);
 
 impl<A: Automaton, Lexeme> Lexer<A, Lexeme> {
-  fn parse(&self, input: String) -> LexerRunner<A, Lexeme> {
+  pub fn parse(&self, input: String) -> LexerRunner<'_, A, Lexeme> {
     LexerRunner {
       input,
       input_idx: 0,
-      lexer: &self,
+      lexer: self,
     }
   }
 }
 
-struct LexerRunner<'a, A: Automaton, Lexeme> {
+pub struct LexerRunner<'a, A: Automaton, Lexeme> {
   input: String,
   input_idx: usize,
   lexer: &'a Lexer<A, Lexeme>,
@@ -21,54 +25,23 @@ struct LexerRunner<'a, A: Automaton, Lexeme> {
 
 impl<'a, A: Automaton, Lexeme> Iterator for LexerRunner<'a, A, Lexeme> {
   type Item = Lexeme;
+
+  // the longest lexeme any rule matches, the earlier rule wins a tie. Stops at the
+  // end of the input or where no rule matches a non-empty lexeme.
   fn next(&mut self) -> Option<Self::Item> {
-    if self.input_idx == self.input.len() {
-      return None;
-    }
-    let mut state = vec![];
-    let mut last_priority_action_idx: Option<(usize, usize)> = None;
-    for (lidx, (l, _action)) in self.lexer.0.iter().enumerate() {
-      let s = l.init_state();
-      if !l.is_dead(&s) {
-        state.push((lidx, s));
-      }
-    }
-    for curr_input_idx in self.input_idx..self.input.len() {
-      let chr = self.input.as_bytes()[curr_input_idx] as char;
-      let mut next_state = vec![];
-      let mut curr_priority_lidx = None;
-      for (lidx, s) in state {
-        let ref lexer = self.lexer.0[lidx].0;
-        let new_s = lexer.transition(&s, chr);
-        if lexer.is_dead(&new_s) {
-          continue;
-        }
-        if lexer.is_accept(&new_s) {
-          if curr_priority_lidx.is_none() {
-            curr_priority_lidx = Some(lidx);
-          }
+    let rest = &self.input[self.input_idx..];
+    let mut best: Option<(usize, usize)> = None;
+    for (lidx, (automaton, _action)) in self.lexer.0.iter().enumerate() {
+      if let Some(len) = automaton.longest_prefix_match(rest) {
+        if len > best.map_or(0, |(best_len, _)| best_len) {
+          best = Some((len, lidx));
         }
-        next_state.push((lidx, new_s));
       }
-      if let Some(lidx) = curr_priority_lidx {
-        last_priority_action_idx = Some((curr_input_idx, lidx));
-      }
-      if next_state.is_empty() {
-        break;
-      }
-      state = next_state;
-    }
-
-    match last_priority_action_idx {
-      Some((input_end_idx, lidx)) => {
-        let new_input_idx = input_end_idx + 1;
-        let lexeme = &self.input[self.input_idx..new_input_idx];
-        self.input_idx = new_input_idx;
-        let token = (self.lexer.0[lidx].1)(lexeme);
-        Some(token)
-      },
-      None => None, // parse ended
     }
+    let (len, lidx) = best?;
+    let lexeme = &rest[..len];
+    self.input_idx += len;
+    Some((self.lexer.0[lidx].1)(lexeme))
   }
 }
 
@@ -77,7 +50,8 @@ mod tests {
 
   use super::*;
   use super::super::dfa::DFAOne;
-  use super::super::dfa_regexp;
+  use super::super::aho_corasick::AhoCorasick;
+  use super::super::nfa::NFAOne;
 
   #[test]
   fn arithmetic_lexeme() {
@@ -111,4 +85,38 @@ mod tests {
       &[Token::Number(1), Token::Plus, Token::Number(23), Token::Subtract, Token::LeftParen, Token::Number(3), Token::Multiply, Token::Number(45), Token::Divide, Token::Number(5), Token::RightParen]
     );
   }
+
+  #[test]
+  fn generic_over_automata() {
+    #[derive(Debug, PartialEq, Eq)]
+    enum Token {
+      Keyword(String),
+      Ident(String),
+      Space,
+    }
+
+    // keywords come first so they win over identifiers of the same length
+    let lexer = Lexer(vec![
+      (NFAOne::from_regexp("if|else|λ"), Box::new(|s: &str| Token::Keyword(s.to_string())) as Action<Token>),
+      (NFAOne::from_regexp("\\p{L}+"), Box::new(|s: &str| Token::Ident(s.to_string()))),
+      (NFAOne::from_regexp(" +"), Box::new(|_| Token::Space)),
+    ]);
+    assert_eq!(
+      lexer.parse("if iffy λ λx".to_string()).collect::<Vec<_>>(),
+      &[
+        Token::Keyword("if".to_string()),
+        Token::Space,
+        Token::Ident("iffy".to_string()),
+        Token::Space,
+        Token::Keyword("λ".to_string()),
+        Token::Space,
+        Token::Ident("λx".to_string()),
+      ]
+    );
+
+    let words = Lexer(vec![
+      (AhoCorasick::new(&["he", "hers", "she"]), Box::new(|s: &str| s.len()) as Action<usize>),
+    ]);
+    assert_eq!(words.parse("hersheshe!".to_string()).collect::<Vec<_>>(), &[4, 2, 3]);
+  }
 }
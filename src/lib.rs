@@ -1,8 +1,25 @@
-mod automaton;
-mod dfa;
-mod nfa;
-mod convert_nfa_to_dfa;
-mod nfa_regexp;
-mod dfa_regexp;
-mod regop;
-mod escape_chars;
\ No newline at end of file
+pub mod aho_corasick;
+pub mod automaton;
+pub mod backtrack;
+pub mod bit_parallel;
+pub mod char_class;
+pub mod dfa;
+pub mod nfa;
+pub mod convert_nfa_to_dfa;
+pub mod nfa_regexp;
+pub mod dfa_regexp;
+pub mod derivative;
+pub mod equivalence;
+pub mod flags;
+pub mod language_ops;
+pub mod lazy_dfa;
+pub mod lexer;
+pub mod prefilter;
+pub mod regexp_parser;
+pub mod simplify;
+pub mod state_elimination;
+pub mod trace;
+pub mod utf8;
+pub mod witness;
+mod escape_chars;
+mod unicode_tables;
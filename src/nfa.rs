@@ -2,6 +2,9 @@ use super::automaton::Automaton;
 
 pub type NFAState = Vec<usize>;
 
+// 256KB of visited bits for the backtracking simulation
+pub const BACKTRACK_VISITED_BUDGET: usize = 256 * 1024 * 8;
+
 pub struct NFAOne {
   pub states_size: usize,
   pub start: usize,
@@ -53,6 +56,26 @@ impl NFAOne {
     NFAOne::gen_state(has_state)
   }
 
+  // whether an accept state can be reached from each state, reading the chars of `input`
+  pub fn co_reachable(&self, input: &str) -> Vec<bool> {
+    let mut from_states: Vec<Vec<usize>> = vec![vec![]; self.states_size];
+    for s in 0..self.states_size {
+      let by_chars = input.chars().flat_map(|chr| (self.transition_func)(s, Some(chr)));
+      for t in (self.transition_func)(s, None).into_iter().chain(by_chars) {
+        from_states[t].push(s);
+      }
+    }
+    let mut co_reachable = vec![false; self.states_size];
+    let mut stack = self.accept.clone();
+    while let Some(curr) = stack.pop() {
+      if !co_reachable[curr] {
+        co_reachable[curr] = true;
+        stack.extend(&from_states[curr]);
+      }
+    }
+    co_reachable
+  }
+
   #[allow(dead_code)]
   fn simulate_by_converting_to_dfa(&self, s: &str) -> bool {
     let mut curr_state = self.e_closure(vec![self.start]);
@@ -62,47 +85,124 @@ impl NFAOne {
     self.is_state_accept(&curr_state)
   }
 
-  fn simulate_on_the_fly(&self, s: &str) -> bool {
-    let mut curr_stack = vec![];
-    let mut next_stack = vec![];
-    let mut already_on = vec![false; self.states_size];
-    fn add_state(
-      s: usize,
-      next_stack: &mut Vec<usize>,
-      already_on: &mut Vec<bool>,
-      trans_func: &Box<dyn Fn(usize, Option<char>) -> NFAState>,
-    ) {
-      next_stack.push(s);
-      already_on[s] = true;
-      // add state and also calculate e-closure
-      for t in trans_func(s, None) {
-        if !already_on[t] {
-          add_state(t, next_stack, already_on, trans_func);
+  // depth first search over (state, input position) pairs, each pair is visited at most
+  // once so the running time stays linear in states_size * input length. It only answers
+  // whether `s` matches: NFAOne has no capture groups, BacktrackOne records those.
+  fn simulate_by_backtracking(&self, s: &[char]) -> bool {
+    let positions = s.len() + 1;
+    let mut visited = vec![0u64; (self.states_size * positions).div_ceil(64)];
+    let mut stack = vec![(self.start, 0)];
+    while let Some((state, pos)) = stack.pop() {
+      let bit = state * positions + pos;
+      if visited[bit / 64] & (1 << (bit % 64)) != 0 {
+        continue;
+      }
+      visited[bit / 64] |= 1 << (bit % 64);
+      if pos == s.len() && self.accept.contains(&state) {
+        return true;
+      }
+      if pos < s.len() {
+        for t in (self.transition_func)(state, Some(s[pos])) {
+          stack.push((t, pos + 1));
+        }
+      }
+      for t in (self.transition_func)(state, None) {
+        stack.push((t, pos));
+      }
+    }
+    false
+  }
+
+  // backtracking is used while its visited set fits in `visited_budget` bits,
+  // longer inputs are simulated on the fly
+  pub fn test_with_budget(&self, s: &str, visited_budget: usize) -> bool {
+    let chars: Vec<char> = s.chars().collect();
+    if self.states_size.saturating_mul(chars.len() + 1) <= visited_budget {
+      self.simulate_by_backtracking(&chars)
+    } else {
+      self.simulate_on_the_fly(s)
+    }
+  }
+
+  // add `s` and everything reachable from it by e to `set`, using `stack` instead of
+  // recursion so long e chains can't overflow the call stack
+  fn add_state(&self, s: usize, set: &mut SparseSet, stack: &mut Vec<usize>) {
+    stack.push(s);
+    while let Some(curr) = stack.pop() {
+      if set.insert(curr) {
+        for t in (self.transition_func)(curr, None) {
+          if !set.contains(t) {
+            stack.push(t);
+          }
         }
       }
     }
+  }
+
+  fn simulate_on_the_fly(&self, s: &str) -> bool {
+    let mut curr_set = SparseSet::new(self.states_size);
+    let mut next_set = SparseSet::new(self.states_size);
+    let mut stack = vec![];
 
     // init e-closure of s0
-    add_state(self.start, &mut curr_stack, &mut already_on, &self.transition_func);
-    for &s in &curr_stack { already_on[s] = false; } // reset already_on
+    self.add_state(self.start, &mut curr_set, &mut stack);
 
     for chr in s.chars() {
-      
-      for state in curr_stack {
+      if curr_set.is_empty() {
+        return false;
+      }
+      next_set.clear();
+      for &state in curr_set.iter() {
         for t in (self.transition_func)(state, Some(chr)) {
-          if !already_on[t] {
-            next_stack.push(t);
-            add_state(t, &mut next_stack, &mut already_on, &self.transition_func);
-          }
+          self.add_state(t, &mut next_set, &mut stack);
         }
       }
-      curr_stack = next_stack;
-      next_stack = vec![];
-      for &s in &curr_stack {
-        already_on[s] = false; // reset
-      }
+      std::mem::swap(&mut curr_set, &mut next_set);
     }
-    self.is_state_accept(&curr_stack)
+    self.accept.iter().any(|&s| curr_set.contains(s))
+  }
+}
+
+// set of states below a fixed size with O(1) insert, lookup and clear,
+// iterating it visits the states in insertion order
+struct SparseSet {
+  dense: Vec<usize>,
+  sparse: Vec<usize>,
+}
+
+impl SparseSet {
+  fn new(size: usize) -> Self {
+    SparseSet {
+      dense: Vec::with_capacity(size),
+      sparse: vec![0; size],
+    }
+  }
+
+  fn contains(&self, s: usize) -> bool {
+    let idx = self.sparse[s];
+    idx < self.dense.len() && self.dense[idx] == s
+  }
+
+  // returns false when `s` was already in the set
+  fn insert(&mut self, s: usize) -> bool {
+    if self.contains(s) {
+      return false;
+    }
+    self.sparse[s] = self.dense.len();
+    self.dense.push(s);
+    true
+  }
+
+  fn clear(&mut self) {
+    self.dense.clear();
+  }
+
+  fn is_empty(&self) -> bool {
+    self.dense.is_empty()
+  }
+
+  fn iter(&self) -> std::slice::Iter<'_, usize> {
+    self.dense.iter()
   }
 }
 
@@ -119,12 +219,11 @@ impl Automaton for NFAOne {
     self.is_state_accept(s)
   }
   fn transition(&self, s: &Self::State, chr: char) -> Self::State {
-    self.transition(s, chr)
+    self.e_closure(self.transition(s, chr))
   }
 
   fn test(&self, s: &str) -> bool {
-    // self.simulate_by_converting_to_dfa(s)
-    self.simulate_on_the_fly(s)
+    self.test_with_budget(s, BACKTRACK_VISITED_BUDGET)
   }
 }
 
@@ -163,4 +262,49 @@ mod tests {
     assert!(nfa_one.test("abb"));
     assert!(!nfa_one.test("ab"));
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn sparse_set_works() {
+    let mut set = SparseSet::new(5);
+    assert!(set.insert(3));
+    assert!(set.insert(1));
+    assert!(!set.insert(3));
+    assert!(set.contains(1));
+    assert!(!set.contains(0));
+    assert_eq!(set.iter().copied().collect::<Vec<_>>(), vec![3, 1]);
+    set.clear();
+    assert!(set.is_empty());
+    assert!(!set.contains(3));
+  }
+
+  #[test]
+  fn long_e_chain() {
+    // every a? adds an e edge skipping it, so the start closure walks all 100k states
+    let nfa = NFAOne::from_regexp(&format!("{}b", "a?".repeat(25_000)));
+    assert!(nfa.states_size > 100_000);
+    assert!(nfa.simulate_on_the_fly("b"));
+    assert!(nfa.simulate_on_the_fly("aaab"));
+    assert!(!nfa.simulate_on_the_fly("aaa"));
+    assert!(!nfa.simulate_on_the_fly("bb"));
+  }
+
+  #[test]
+  fn backtracking_matches_on_the_fly() {
+    let regexp = NFAOne::from_regexp("(a|bc)*(abb|c?)");
+    let inputs = ["", "a", "abb", "bcabb", "bcbcc", "abcb", "abbc", "bcabbabb", "cc"];
+    for s in &inputs {
+      let chars: Vec<char> = s.chars().collect();
+      assert_eq!(regexp.simulate_by_backtracking(&chars), regexp.simulate_on_the_fly(s), "{:?}", s);
+      assert_eq!(regexp.test_with_budget(s, 0), regexp.test_with_budget(s, usize::MAX), "{:?}", s);
+    }
+  }
+
+  #[test]
+  fn backtracking_stays_linear() {
+    // a plain backtracker takes exponential time on this one
+    let regexp = NFAOne::from_regexp("(a*)*(a*)*b");
+    let input = "a".repeat(200);
+    assert!(!regexp.test_with_budget(&input, usize::MAX));
+    assert!(regexp.test_with_budget(&format!("{}b", input), usize::MAX));
+  }
+}
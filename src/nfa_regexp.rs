@@ -1,8 +1,11 @@
 use super::nfa::*;
+use super::backtrack::{BacktrackOne, SpecialEdge};
+use super::char_class::CharClass;
+use super::dfa_regexp::NodeType;
 use std::collections::HashMap;
-use super::automaton::Automaton;
-use super::regop::RegOp;
-use super::escape_chars::{EscapeChars, MaybeEsc};
+use super::regexp_parser::{self, Anchor, Ast, AstKind, Boundary, GroupKind};
+use super::flags::Flags;
+use super::utf8::utf8_sequences;
 
 struct NFABasic {
   start: usize,
@@ -10,10 +13,28 @@ struct NFABasic {
 }
 
 type TransitionMapType = HashMap<(usize, Option<char>), Vec<usize>>;
+type ClassMapType = HashMap<usize, Vec<(CharClass, usize)>>;
+
+// a part of the NFA under construction with its states numbered from 0, see
+// NFAConstructor::record
+struct Fragment {
+  states_size: usize,
+  start: usize,
+  accept: usize,
+  edges: Vec<(usize, Option<char>, usize)>,
+  class_edges: Vec<(usize, CharClass, usize)>,
+  special_edges: Vec<(usize, SpecialEdge, usize)>,
+}
 
 struct NFAConstructor {
   state_idx: usize,
   transition_map: TransitionMapType,
+  class_map: ClassMapType, // edges taken by any char of a range set
+  special_map: HashMap<usize, Vec<(SpecialEdge, usize)>>,
+  groups: usize,
+  guard_loops: bool, // whether loops need their body to consume input, see closure_basic
+  loops: usize,
+  utf8: Option<bool>, // Some(any_byte) to read UTF-8 bytes instead of chars, see construct_class
 }
 
 impl NFAConstructor {
@@ -21,6 +42,12 @@ impl NFAConstructor {
     NFAConstructor {
       state_idx: 0,
       transition_map: HashMap::new(),
+      class_map: HashMap::new(),
+      special_map: HashMap::new(),
+      groups: 0,
+      guard_loops: false,
+      loops: 0,
+      utf8: None,
     }
   }
 
@@ -34,6 +61,18 @@ impl NFAConstructor {
     self.transition_map.entry((from, by)).or_insert(vec![]).push(to);
   }
 
+  // the states `state` goes to reading `input`, special edges aside
+  fn successors(&self, state: usize, input: Option<char>) -> Vec<usize> {
+    let mut res = match self.transition_map.get(&(state, input)) {
+      Some(states) => states.clone(),
+      None => vec![],
+    };
+    if let (Some(chr), Some(edges)) = (input, self.class_map.get(&state)) {
+      res.extend(edges.iter().filter(|(class, _)| class.contains(chr)).map(|&(_, to)| to));
+    }
+    res
+  }
+
   fn construct_singleton(&mut self, input: Option<char>) -> NFABasic {
     let start = self.gen_new_state_idx();
     let accept = self.gen_new_state_idx();
@@ -52,6 +91,54 @@ impl NFAConstructor {
     self.construct_singleton(Some(chr))
   }
 
+  fn construct_class(&mut self, class: CharClass) -> NFABasic {
+    if let Some(any_byte) = self.utf8 {
+      return self.construct_utf8_class(&class, any_byte);
+    }
+    if let Some(chr) = class.as_single() {
+      return self.construct_single_char(chr);
+    }
+    let start = self.gen_new_state_idx();
+    let accept = self.gen_new_state_idx();
+    self.class_map.entry(start).or_default().push((class, accept));
+    NFABasic {
+      start,
+      accept,
+    }
+  }
+
+  // the byte sequences encoding the chars of `class`, byte b read as the char U+00b. With
+  // `any_byte`, \p{Any} also takes a single byte of invalid UTF-8.
+  fn construct_utf8_class(&mut self, class: &CharClass, any_byte: bool) -> NFABasic {
+    let start = self.gen_new_state_idx();
+    let accept = self.gen_new_state_idx();
+    for &(lo, hi) in class.ranges() {
+      for sequence in utf8_sequences(lo, hi) {
+        let mut from = start;
+        for (idx, &range) in sequence.iter().enumerate() {
+          let to = if idx + 1 == sequence.len() { accept } else { self.gen_new_state_idx() };
+          self.add_byte_range(from, range, to);
+          from = to;
+        }
+      }
+    }
+    if any_byte && class.ranges() == [('\0', char::MAX)] {
+      self.add_byte_range(start, (0, 255), accept);
+    }
+    NFABasic {
+      start,
+      accept,
+    }
+  }
+
+  fn add_byte_range(&mut self, from: usize, (lo, hi): (u8, u8), to: usize) {
+    if lo == hi {
+      self.add_new_transition(from, Some(lo as char), to);
+    } else {
+      self.class_map.entry(from).or_default().push((CharClass::new(vec![(lo as char, hi as char)]), to));
+    }
+  }
+
   fn union(&mut self, nfa_a: NFABasic, nfa_b: NFABasic) -> NFABasic {
     let start = self.gen_new_state_idx();
     let accept = self.gen_new_state_idx();
@@ -81,13 +168,24 @@ impl NFAConstructor {
   ) -> NFABasic {
     let start = self.gen_new_state_idx();
     let end = self.gen_new_state_idx();
-    self.add_new_transition(start, None, nfa.start);
+    if has_inner_end_to_start && self.guard_loops {
+      // a backtracked loop goes around again only after its body consumed input, or
+      // an iteration matching the empty string could repeat with other captures forever
+      let entry = self.gen_new_state_idx();
+      let loop_idx = self.loops;
+      self.loops += 1;
+      self.add_new_transition(start, None, entry);
+      self.special_map.entry(entry).or_default().push((SpecialEdge::Mark(loop_idx), nfa.start));
+      self.special_map.entry(nfa.accept).or_default().push((SpecialEdge::Progress(loop_idx), entry));
+    } else {
+      self.add_new_transition(start, None, nfa.start);
+    }
     self.add_new_transition(nfa.accept, None, end);
 
     if has_start_to_end {
       self.add_new_transition(start, None, end);
     }
-    if has_inner_end_to_start {
+    if has_inner_end_to_start && !self.guard_loops {
       self.add_new_transition(nfa.accept, None, nfa.start);
     }
     NFABasic {
@@ -107,133 +205,314 @@ impl NFAConstructor {
   fn question_mark(&mut self, nfa: NFABasic) -> NFABasic {
     self.closure_basic(nfa, true, false)
   }
-}
-
-struct StackFrame {
-  op_stack: Vec<RegOp>,
-  item_stack: Vec<NFABasic>,
-}
 
-impl NFAOne {
-  pub fn from_regexp(reg_exp: &str) -> Self {
-    let mut nfa_constructor = NFAConstructor::new();
-    let mut stack: Vec<StackFrame> = vec![StackFrame {
-      op_stack: vec![RegOp::Eof],
-      item_stack: vec![]
-    }];
-
-    fn reduce_frame(frame: &mut StackFrame, nfa_constructor: &mut NFAConstructor, new_op: RegOp) {
-      while let Some(top_op) = frame.op_stack.pop() {
-        if top_op.get_priority() < new_op.get_priority() {
-          frame.op_stack.push(top_op); // push back, top_op has lower priority
-          break;
+  // the states reachable from `nfa.start`, lookaround bodies included, renumbered from 0
+  fn record(&self, nfa: &NFABasic) -> Fragment {
+    let mut edges: HashMap<usize, Vec<(Option<char>, usize)>> = HashMap::new();
+    for (&(from, by), to_states) in &self.transition_map {
+      edges.entry(from).or_default().extend(to_states.iter().map(|&to| (by, to)));
+    }
+    let mut state_map: HashMap<usize, usize> = HashMap::new();
+    let mut order = vec![];
+    let mut stack = vec![nfa.start];
+    while let Some(state) = stack.pop() {
+      if state_map.contains_key(&state) {
+        continue;
+      }
+      state_map.insert(state, order.len());
+      order.push(state);
+      stack.extend(edges.get(&state).into_iter().flatten().map(|&(_, to)| to));
+      stack.extend(self.class_map.get(&state).into_iter().flatten().map(|&(_, to)| to));
+      for &(edge, to) in self.special_map.get(&state).into_iter().flatten() {
+        stack.push(to);
+        if let SpecialEdge::Look { start, accept, .. } = edge {
+          stack.push(start);
+          stack.push(accept);
         }
-        match top_op {
-          RegOp::Eof | RegOp::Paren => {}, // do nothing
-          RegOp::Union | RegOp::Concat => { // binary operator
-            let operand_right = frame.item_stack.pop().expect(&format!("parse fail at {:?}", top_op));
-            let operand_left = frame.item_stack.pop().expect(&format!("parse fail at {:?}", top_op));
-            frame.item_stack.push(
-              match top_op {
-                RegOp::Union => nfa_constructor.union(operand_left, operand_right),
-                RegOp::Concat => nfa_constructor.concat(operand_left, operand_right),
-                _ => unreachable!(),
-              }
-            );
-          },
-          RegOp::Closure | RegOp::Plus | RegOp::Question => {
-            let operand = frame.item_stack.pop().expect(&format!("parse fail at {:?}", top_op));
-            frame.item_stack.push(
-              match top_op {
-                RegOp::Closure => nfa_constructor.closure(operand),
-                RegOp::Plus => nfa_constructor.closure_plus(operand),
-                RegOp::Question => nfa_constructor.question_mark(operand),
-                _ => unreachable!(),
-              }
-            );
+      }
+    }
+
+    let mut res = Fragment {
+      states_size: order.len(),
+      start: state_map[&nfa.start],
+      accept: state_map[&nfa.accept],
+      edges: vec![],
+      class_edges: vec![],
+      special_edges: vec![],
+    };
+    for (idx, state) in order.into_iter().enumerate() {
+      for &(by, to) in edges.get(&state).into_iter().flatten() {
+        res.edges.push((idx, by, state_map[&to]));
+      }
+      for (class, to) in self.class_map.get(&state).into_iter().flatten() {
+        res.class_edges.push((idx, class.clone(), state_map[to]));
+      }
+      for &(edge, to) in self.special_map.get(&state).into_iter().flatten() {
+        let edge = match edge {
+          SpecialEdge::Look { behind, negated, start, accept } => SpecialEdge::Look {
+            behind,
+            negated,
+            start: state_map[&start],
+            accept: state_map[&accept],
           },
-        }
+          _ => edge,
+        };
+        res.special_edges.push((idx, edge, state_map[&to]));
       }
-      frame.op_stack.push(new_op);
     }
+    res
+  }
 
-    let mut is_last_reg_item = false;
-    for chr in EscapeChars::new(reg_exp.chars()) {
-      match chr {
-        MaybeEsc::NonEsc('(') => {
-          if is_last_reg_item { 
-            reduce_frame(stack.last_mut().unwrap(), &mut nfa_constructor, RegOp::Concat);
-          }
-          stack.push(StackFrame { op_stack: vec![RegOp::Paren], item_stack: vec![] });
-          is_last_reg_item = false;
-        },
-        MaybeEsc::NonEsc(')') => {
-          let mut current_frame = stack.pop().expect("parse error at )");
-          reduce_frame(&mut current_frame, &mut nfa_constructor, RegOp::Paren);
-          assert!(current_frame.item_stack.len() <= 1, "current frame error");
-          let frame_res = if current_frame.item_stack.is_empty() {
-            nfa_constructor.construct_e()
-          } else {
-            current_frame.item_stack.pop().unwrap()
-          };
-          stack.last_mut().expect("stack empty error").item_stack.push(frame_res);
-          is_last_reg_item = true;
+  // a fresh copy of a recorded fragment
+  fn stamp(&mut self, fragment: &Fragment) -> NFABasic {
+    let base = self.state_idx;
+    self.state_idx += fragment.states_size;
+    for &(from, by, to) in &fragment.edges {
+      self.add_new_transition(base + from, by, base + to);
+    }
+    for (from, class, to) in &fragment.class_edges {
+      self.class_map.entry(base + from).or_default().push((class.clone(), base + to));
+    }
+    for &(from, edge, to) in &fragment.special_edges {
+      let edge = match edge {
+        SpecialEdge::Look { behind, negated, start, accept } => SpecialEdge::Look {
+          behind,
+          negated,
+          start: base + start,
+          accept: base + accept,
         },
-        MaybeEsc::NonEsc('|') => {
-          reduce_frame(stack.last_mut().unwrap(), &mut nfa_constructor, RegOp::Union);
-          is_last_reg_item = false;
+        _ => edge,
+      };
+      self.special_map.entry(base + from).or_default().push((edge, base + to));
+    }
+    NFABasic {
+      start: base + fragment.start,
+      accept: base + fragment.accept,
+    }
+  }
+
+  // min copies of `nfa`, then max - min optional copies or a closure when unbounded
+  fn repeat(&mut self, nfa: NFABasic, min: usize, max: Option<usize>) -> NFABasic {
+    let total = min + max.map_or(1, |max| max - min);
+    if total == 0 {
+      return self.construct_e();
+    }
+    // copy before wrapping anything, the copies must not reach outside the fragment.
+    // It is recorded once so every copy costs only its own size.
+    let fragment = self.record(&nfa);
+    let mut pieces: Vec<NFABasic> = (1..total).map(|_| self.stamp(&fragment)).collect();
+    pieces.insert(0, nfa);
+    let mut res: Option<NFABasic> = None;
+    for (idx, piece) in pieces.into_iter().enumerate() {
+      let piece = if idx < min {
+        piece
+      } else if max.is_none() {
+        self.closure(piece)
+      } else {
+        self.question_mark(piece)
+      };
+      res = Some(match res {
+        None => piece,
+        Some(prev) => self.concat(prev, piece),
+      });
+    }
+    res.unwrap()
+  }
+
+  fn construct_special(&mut self, edge: SpecialEdge) -> NFABasic {
+    let start = self.gen_new_state_idx();
+    let accept = self.gen_new_state_idx();
+    self.special_map.entry(start).or_default().push((edge, accept));
+    NFABasic {
+      start,
+      accept,
+    }
+  }
+
+  fn capture(&mut self, nfa: NFABasic, group: usize) -> NFABasic {
+    let open = self.construct_special(SpecialEdge::Save(group * 2));
+    let close = self.construct_special(SpecialEdge::Save(group * 2 + 1));
+    let inner = self.concat(open, nfa);
+    self.concat(inner, close)
+  }
+
+  fn lookaround(&mut self, nfa: NFABasic, behind: bool, negated: bool) -> NFABasic {
+    self.construct_special(SpecialEdge::Look { behind, negated, start: nfa.start, accept: nfa.accept })
+  }
+}
+
+// Thompson construction of the parsed `ast`. The extended syntax adds capture groups,
+// backreferences \1 to \9 and lookaround (?=..) (?!..) (?<=..) (?<!..)
+fn build(ast: &Ast, nfa_constructor: &mut NFAConstructor, extended: bool) -> NFABasic {
+  match &ast.kind {
+    AstKind::Empty => nfa_constructor.construct_e(),
+    AstKind::Char(_) | AstKind::Class(_) => nfa_constructor.construct_class(ast.leaf_class().unwrap()),
+    AstKind::Anchor(anchor) => {
+      assert!(nfa_constructor.utf8.is_none(), "^ and $ are only supported by DFAOne, NFAOne and BacktrackOne");
+      nfa_constructor.construct_special(SpecialEdge::Assert(*anchor))
+    },
+    AstKind::Backref(group) => {
+      assert!(extended, "backreference \\{} is not regular, use BacktrackOne::from_regexp", group);
+      nfa_constructor.construct_special(SpecialEdge::Backref(*group))
+    },
+    AstKind::Group { kind, child } => {
+      let inner = build(child, nfa_constructor, extended);
+      match *kind {
+        GroupKind::NonCapture => inner,
+        GroupKind::Capture(_) if !extended => inner,
+        GroupKind::Capture(group) => {
+          nfa_constructor.groups = nfa_constructor.groups.max(group);
+          nfa_constructor.capture(inner, group)
         },
-         MaybeEsc::NonEsc('*')
-         | MaybeEsc::NonEsc('?')
-         | MaybeEsc::NonEsc('+') => {
-          reduce_frame(stack.last_mut().unwrap(), &mut nfa_constructor, match chr.get_chr() {
-            '*' => RegOp::Closure,
-            '?' => RegOp::Question,
-            '+' => RegOp::Plus,
-            _ => unreachable!(),
-          });
-          is_last_reg_item = true;
+        GroupKind::Look { behind, negated } => {
+          assert!(extended, "lookaround is not regular, use BacktrackOne::from_regexp");
+          nfa_constructor.lookaround(inner, behind, negated)
         },
-        maybe_esc_chr => { // alphabet like a,b,c,d
-          let chr = maybe_esc_chr.get_chr();
-          if is_last_reg_item { 
-            reduce_frame(stack.last_mut().unwrap(), &mut nfa_constructor, RegOp::Concat);
-          }
-          stack.last_mut().expect("no stack frame").item_stack.push(nfa_constructor.construct_single_char(chr));
-          is_last_reg_item = true;
-        }
       }
-    }
-    // finally reduce frame with RegOp::Eof
+    },
+    AstKind::Unary { node_type: NodeType::Complement, .. }
+      | AstKind::List { node_type: NodeType::Intersect, .. } => {
+      panic!("& and ~ are only supported by DFAOne::from_regexp")
+    },
+    AstKind::Unary { node_type, child } => {
+      let operand = build(child, nfa_constructor, extended);
+      match *node_type {
+        NodeType::Closure => nfa_constructor.closure(operand),
+        NodeType::Plus => nfa_constructor.closure_plus(operand),
+        NodeType::Question => nfa_constructor.question_mark(operand),
+        NodeType::Repeat { min, max } => nfa_constructor.repeat(operand, min, max),
+        _ => unreachable!(),
+      }
+    },
+    AstKind::List { node_type, children } => {
+      let mut res = build(&children[0], nfa_constructor, extended);
+      for child in &children[1..] {
+        let right = build(child, nfa_constructor, extended);
+        res = match node_type {
+          NodeType::Union => nfa_constructor.union(res, right),
+          NodeType::Concat => nfa_constructor.concat(res, right),
+          _ => unreachable!(),
+        };
+      }
+      res
+    },
+  }
+}
 
-    assert!(stack.len() == 1, "parse error: stack should be reduced to 1");
-    let mut stack_frame = stack.pop().unwrap();
-    reduce_frame(&mut stack_frame, &mut nfa_constructor, RegOp::Eof);
+fn parse_regexp(reg_exp: &str, flags: Flags, nfa_constructor: &mut NFAConstructor, extended: bool) -> NFABasic {
+  build(&regexp_parser::parse_with_flags(reg_exp, flags), nfa_constructor, extended)
+}
 
-    assert!(stack_frame.item_stack.len() <= 1);
-    let res = if stack_frame.item_stack.is_empty() {
-      nfa_constructor.construct_e()
-    } else {
-      stack_frame.item_stack.pop().unwrap()
-    };
+impl NFAOne {
+  pub fn from_regexp(reg_exp: &str) -> Self {
+    NFAOne::from_regexp_with_flags(reg_exp, Flags::default())
+  }
+
+  pub fn from_regexp_with_flags(reg_exp: &str, flags: Flags) -> Self {
+    let mut nfa_constructor = NFAConstructor::new();
+    let res = parse_regexp(reg_exp, flags, &mut nfa_constructor, false);
+    NFAOne::from_constructor(nfa_constructor, res)
+  }
+
+  // NFA over the UTF-8 encoding of the matched strings, run it with `utf8::test_bytes`.
+  // With `any_byte`, \p{Any} also matches a single byte of invalid UTF-8.
+  pub fn bytes_from_regexp(reg_exp: &str, any_byte: bool) -> Self {
+    let mut nfa_constructor = NFAConstructor::new();
+    nfa_constructor.utf8 = Some(any_byte);
+    let res = parse_regexp(reg_exp, Flags::default(), &mut nfa_constructor, false);
+    NFAOne::from_constructor(nfa_constructor, res)
+  }
+
+  fn from_constructor(nfa_constructor: NFAConstructor, res: NFABasic) -> Self {
+    if !nfa_constructor.special_map.is_empty() {
+      return NFAOne::with_anchors(nfa_constructor, res);
+    }
     NFAOne {
       states_size: nfa_constructor.state_idx,
       start: res.start,
       accept: vec![res.accept],
       transition_func: Box::new(move |state: usize, input: Option<char>| {
-        match nfa_constructor.transition_map.get(&(state, input)) {
-          Some(states) => states.clone(),
-          None => vec![],
+        nfa_constructor.successors(state, input)
+      })
+    }
+  }
+
+  // ^ and $ need the chars around the position, so every state is paired with the
+  // boundary before it and the strictest $ waiting for the next char, numbered
+  // state * 9 + boundary * 3 + pending
+  fn with_anchors(nfa_constructor: NFAConstructor, res: NFABasic) -> Self {
+    const BOUNDARIES: [Boundary; 3] = [Boundary::Text, Boundary::NewLine, Boundary::Other];
+    const PENDING: [Option<Anchor>; 3] = [None, Some(Anchor::EndLine), Some(Anchor::EndText)];
+    fn encode(state: usize, before: Boundary, pending: Option<Anchor>) -> usize {
+      let before = BOUNDARIES.iter().position(|&b| b == before).unwrap();
+      state * 9 + before * 3 + PENDING.iter().position(|&p| p == pending).unwrap()
+    }
+    NFAOne {
+      states_size: nfa_constructor.state_idx * 9,
+      start: encode(res.start, Boundary::Text, None),
+      // the text ends here, so every $ holds
+      accept: (0..9).map(|idx| res.accept * 9 + idx).collect(),
+      transition_func: Box::new(move |state: usize, input: Option<char>| {
+        let (before, pending) = (BOUNDARIES[state / 3 % 3], PENDING[state % 3]);
+        let state = state / 9;
+        let chr = match input {
+          Some(chr) => chr,
+          None => {
+            let mut res: Vec<usize> = nfa_constructor
+              .successors(state, None)
+              .into_iter()
+              .map(|to| encode(to, before, pending))
+              .collect();
+            for &(edge, to) in nfa_constructor.special_map.get(&state).into_iter().flatten() {
+              match edge {
+                SpecialEdge::Assert(anchor) if anchor.looks_behind() => {
+                  if anchor.holds(before) {
+                    res.push(encode(to, before, pending));
+                  }
+                },
+                SpecialEdge::Assert(anchor) => res.push(encode(to, before, pending.max(Some(anchor)))),
+                _ => unreachable!(), // the regular syntax has no other special edges
+              }
+            }
+            return res;
+          },
+        };
+        let after = Boundary::of(Some(chr));
+        if pending.is_some_and(|anchor| !anchor.holds(after)) {
+          return vec![];
         }
+        nfa_constructor.successors(state, input).into_iter().map(|to| encode(to, after, None)).collect()
       })
     }
   }
 }
 
+impl BacktrackOne {
+  pub fn from_regexp(reg_exp: &str) -> Self {
+    let mut nfa_constructor = NFAConstructor::new();
+    nfa_constructor.guard_loops = true;
+    let res = parse_regexp(reg_exp, Flags::default(), &mut nfa_constructor, true);
+    let mut res = BacktrackOne {
+      states_size: nfa_constructor.state_idx,
+      start: res.start,
+      accept: res.accept,
+      groups: nfa_constructor.groups + 1, // group 0 is never captured
+      loops: nfa_constructor.loops,
+      reaches_backref: vec![],
+      transition_map: nfa_constructor.transition_map,
+      class_map: nfa_constructor.class_map,
+      special_map: nfa_constructor.special_map,
+    };
+    res.reaches_backref = res.states_reaching_backref();
+    res
+  }
+}
+
 
 #[cfg(test)]
 mod tests {
   use super::*;
+  use super::super::automaton::Automaton;
 
   #[test]
   fn regexp_instance_1() {
@@ -258,9 +537,22 @@ mod tests {
     assert!(!regexp.test("abcbcabbc"));
   }
 
+  #[test]
+  fn large_repeat() {
+    let regexp = NFAOne::from_regexp("(ab){200}");
+    // every copy adds the same few states
+    assert!(regexp.states_size < 200 * 8, "{}", regexp.states_size);
+    assert!(regexp.test(&"ab".repeat(200)));
+    assert!(!regexp.test(&"ab".repeat(199)));
+    let regexp = BacktrackOne::from_regexp("((?=a)(a|b)){1,20}c");
+    assert!(regexp.test(&format!("{}c", "a".repeat(20))));
+    assert!(!regexp.test(&format!("{}c", "a".repeat(21))));
+    assert!(!regexp.test("abc"));
+  }
+
   #[test]
   fn regexp_number() {
-    let num_exp = NFAOne::from_regexp("((1|2|3|4|5|6|7|8|9)(0|1|2|3|4|5|6|7|8|9)*|0)(.(0|1|2|3|4|5|6|7|8|9)+)?");
+    let num_exp = NFAOne::from_regexp("((1|2|3|4|5|6|7|8|9)(0|1|2|3|4|5|6|7|8|9)*|0)(\\.(0|1|2|3|4|5|6|7|8|9)+)?");
     assert!(num_exp.test("0"));
     assert!(num_exp.test("4"));
     assert!(num_exp.test("10"));
@@ -276,4 +568,10 @@ mod tests {
     assert!(!num_exp.test("01.123"));
     assert!(!num_exp.test("01."));
   }
-}
\ No newline at end of file
+
+  #[test]
+  #[should_panic(expected = "lookaround is not regular")]
+  fn regexp_rejects_lookaround() {
+    NFAOne::from_regexp("(?!a)b");
+  }
+}
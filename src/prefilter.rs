@@ -0,0 +1,289 @@
+use super::automaton::Automaton;
+use super::bit_parallel::PositionMatcher;
+use super::dfa_regexp::{parse_ast_regexp, NodeType, RegASTNode};
+
+// literals every string matched by a node has to contain
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LiteralInfo {
+  pub exact: Option<String>, // the node only matches this string
+  pub prefix: String,
+  pub suffix: String,
+  pub inner: String, // the longest required substring
+}
+
+fn longest(a: String, b: String) -> String {
+  if b.len() > a.len() { b } else { a }
+}
+
+fn common_prefix(a: &str, b: &str) -> String {
+  a.chars().zip(b.chars()).take_while(|(x, y)| x == y).map(|(x, _)| x).collect()
+}
+
+fn common_suffix(a: &str, b: &str) -> String {
+  let rev: String = a.chars().rev().zip(b.chars().rev()).take_while(|(x, y)| x == y).map(|(x, _)| x).collect();
+  rev.chars().rev().collect()
+}
+
+impl LiteralInfo {
+  fn new_exact(s: String) -> Self {
+    LiteralInfo {
+      exact: Some(s.clone()),
+      prefix: s.clone(),
+      suffix: s.clone(),
+      inner: s,
+    }
+  }
+
+  fn new_unknown() -> Self {
+    LiteralInfo {
+      exact: None,
+      prefix: String::new(),
+      suffix: String::new(),
+      inner: String::new(),
+    }
+  }
+}
+
+pub fn extract_literals(node: &RegASTNode) -> LiteralInfo {
+  match node {
+    // an anchor only narrows down where the empty string matches
+    RegASTNode::Endmarker | RegASTNode::LeafEmpty | RegASTNode::Anchor(_) => LiteralInfo::new_exact(String::new()),
+    RegASTNode::LeafEmptySet | RegASTNode::Class(_) => LiteralInfo::new_unknown(),
+    RegASTNode::Leaf(chr) => LiteralInfo::new_exact(chr.to_string()),
+    RegASTNode::Unary { .. } => LiteralInfo::new_unknown(), // closure may be empty, complement is unknown
+    RegASTNode::Binary { node_type, left_child, right_child } => {
+      let left = extract_literals(left_child);
+      let right = extract_literals(right_child);
+      match node_type {
+        NodeType::Concat => {
+          if let (Some(l), Some(r)) = (&left.exact, &right.exact) {
+            return LiteralInfo::new_exact(format!("{}{}", l, r));
+          }
+          let prefix = match left.exact {
+            Some(ref l) => format!("{}{}", l, right.prefix),
+            None => left.prefix.clone(),
+          };
+          let suffix = match right.exact {
+            Some(ref r) => format!("{}{}", left.suffix, r),
+            None => right.suffix.clone(),
+          };
+          let across = format!("{}{}", left.suffix, right.prefix);
+          let inner = longest(longest(left.inner, right.inner), across);
+          LiteralInfo {
+            exact: None,
+            inner: longest(longest(inner, prefix.clone()), suffix.clone()),
+            prefix,
+            suffix,
+          }
+        },
+        NodeType::Union => {
+          if left.exact.is_some() && left.exact == right.exact {
+            return left;
+          }
+          let prefix = common_prefix(&left.prefix, &right.prefix);
+          let suffix = common_suffix(&left.suffix, &right.suffix);
+          LiteralInfo {
+            exact: None,
+            inner: longest(prefix.clone(), suffix.clone()),
+            prefix,
+            suffix,
+          }
+        },
+        NodeType::Intersect => {
+          // both sides have to hold, keep the more informative one
+          if left.exact.is_some() {
+            return left;
+          }
+          if right.exact.is_some() {
+            return right;
+          }
+          LiteralInfo {
+            exact: None,
+            prefix: longest(left.prefix, right.prefix),
+            suffix: longest(left.suffix, right.suffix),
+            inner: longest(left.inner, right.inner),
+          }
+        },
+        _ => unreachable!(),
+      }
+    },
+  }
+}
+
+pub struct Prefilter {
+  pub literals: LiteralInfo,
+}
+
+impl Prefilter {
+  pub fn from_regexp(reg_exp: &str) -> Self {
+    Prefilter {
+      literals: extract_literals(&parse_ast_regexp(reg_exp)),
+    }
+  }
+
+  // false when `s` can't be matched as a whole
+  pub fn might_match(&self, s: &str) -> bool {
+    s.starts_with(&self.literals.prefix)
+      && s.ends_with(&self.literals.suffix)
+      && s.contains(&self.literals.inner)
+  }
+
+  // the byte indexes from `start` on where a match could start, in order. The
+  // position of the inner literal is kept until the candidates pass it, so the
+  // haystack is only searched for it once.
+  pub fn candidates<'a>(&'a self, haystack: &'a str, start: usize) -> impl Iterator<Item = usize> + 'a {
+    let mut from = start;
+    let mut inner_at: Option<usize> = None;
+    std::iter::from_fn(move || loop {
+      if from > haystack.len() {
+        return None;
+      }
+      if inner_at.is_none_or(|at| at < from) {
+        inner_at = Some(from + haystack[from..].find(&self.literals.inner)?);
+      }
+      let mut prefix_chars = self.literals.prefix.chars();
+      let candidate = match (prefix_chars.next(), prefix_chars.next()) {
+        (None, _) => from,
+        // str::find uses memchr for a single char and two-way search for longer strings
+        (Some(chr), None) => from + haystack[from..].find(chr)?,
+        _ => from + haystack[from..].find(&self.literals.prefix)?,
+      };
+      // a match from the candidate needs the inner literal after it
+      if inner_at.is_some_and(|at| at < candidate) {
+        from = candidate;
+        continue;
+      }
+      from = candidate + haystack[candidate..].chars().next().map_or(1, |chr| chr.len_utf8());
+      return Some(candidate);
+    })
+  }
+}
+
+// runs the automaton only where the prefilter allows a match
+pub struct PrefilteredMatcher<A: Automaton> {
+  pub automaton: A,
+  pub prefilter: Prefilter,
+}
+
+impl<A: Automaton> PrefilteredMatcher<A> {
+  // `build` compiles the same pattern the prefilter is taken from, so the two agree
+  pub fn with_automaton(reg_exp: &str, build: impl FnOnce(&str) -> A) -> Self {
+    PrefilteredMatcher {
+      automaton: build(reg_exp),
+      prefilter: Prefilter::from_regexp(reg_exp),
+    }
+  }
+
+  // leftmost-longest match as a byte range
+  pub fn find(&self, haystack: &str) -> Option<(usize, usize)> {
+    self.find_at(haystack, 0)
+  }
+}
+
+impl PrefilteredMatcher<PositionMatcher> {
+  // the matcher to use when nothing else is known about the pattern: the prefilter
+  // in front of the bit-parallel matcher, or of the Glushkov NFA past MAX_STATES positions
+  pub fn from_regexp(reg_exp: &str) -> Self {
+    PrefilteredMatcher::with_automaton(reg_exp, PositionMatcher::from_regexp)
+  }
+}
+
+impl<A: Automaton> Automaton for PrefilteredMatcher<A> {
+  type State = A::State;
+
+  fn init_state(&self) -> Self::State {
+    self.automaton.init_state()
+  }
+  fn is_dead(&self, s: &Self::State) -> bool {
+    self.automaton.is_dead(s)
+  }
+  fn is_accept(&self, s: &Self::State) -> bool {
+    self.automaton.is_accept(s)
+  }
+  fn transition(&self, s: &Self::State, chr: char) -> Self::State {
+    self.automaton.transition(s, chr)
+  }
+  fn is_match_state_reachable(&self, s: &Self::State) -> bool {
+    self.automaton.is_match_state_reachable(s)
+  }
+
+  fn test(&self, s: &str) -> bool {
+    self.prefilter.might_match(s) && self.automaton.test(s)
+  }
+
+  // only runs the automaton from the candidate positions of the prefilter
+  fn find_at(&self, haystack: &str, start: usize) -> Option<(usize, usize)> {
+    self.prefilter.candidates(haystack, start).find_map(|candidate| {
+      let len = self.automaton.longest_prefix_match(&haystack[candidate..])?;
+      Some((candidate, candidate + len))
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::nfa::NFAOne;
+  use super::super::dfa::DFAOne;
+
+  fn literals(reg_exp: &str) -> (String, String, String) {
+    let info = Prefilter::from_regexp(reg_exp).literals;
+    (info.prefix, info.suffix, info.inner)
+  }
+
+  #[test]
+  fn extract_literals_works() {
+    assert_eq!(literals("abc"), ("abc".into(), "abc".into(), "abc".into()));
+    assert_eq!(literals("ab(c|d)*ef"), ("ab".into(), "ef".into(), "ab".into()));
+    assert_eq!(literals("(a|b)*xyz(a|b)"), ("".into(), "".into(), "xyz".into()));
+    assert_eq!(literals("(abc|abd)x"), ("ab".into(), "x".into(), "ab".into()));
+    assert_eq!(literals("(xab|yab)").1, "ab");
+    assert_eq!(literals("a*"), ("".into(), "".into(), "".into()));
+    assert_eq!(literals("(ab|ab)c").0, "abc");
+  }
+
+  #[test]
+  fn find_works() {
+    let matcher = PrefilteredMatcher::with_automaton("ab(c|d)*e", NFAOne::from_regexp);
+    assert_eq!(matcher.find("xxabcdcex"), Some((2, 8)));
+    assert_eq!(matcher.find("abx abddeab"), Some((4, 9)));
+    assert_eq!(matcher.find("abxabc"), None);
+    assert_eq!(matcher.find("héllo abe"), Some((7, 10)));
+
+    let matcher = PrefilteredMatcher::with_automaton("(a|b)*c", |p| DFAOne::from_regexp(p, "abc"));
+    assert_eq!(matcher.find("xxababcab"), Some((2, 7)));
+    assert_eq!(matcher.find("xxabab"), None);
+  }
+
+  #[test]
+  fn from_regexp_picks_matcher() {
+    let matcher = PrefilteredMatcher::from_regexp("ab(c|d)*e");
+    assert!(matches!(matcher.automaton, PositionMatcher::BitParallel(_)));
+    assert_eq!(matcher.find("xxabcdcex"), Some((2, 8)));
+    assert!(matcher.test("abdce"));
+
+    let large = format!("x(a|b)*{}", "a".repeat(130));
+    let matcher = PrefilteredMatcher::from_regexp(&large);
+    assert!(matches!(matcher.automaton, PositionMatcher::Glushkov(_)));
+    let haystack = format!("aaxbab{}c", "a".repeat(130));
+    assert_eq!(matcher.find(&haystack), Some((2, haystack.len() - 1)));
+  }
+
+  #[test]
+  fn candidates_work() {
+    let prefilter = Prefilter::from_regexp("a(b|c)*xyz");
+    let candidates: Vec<usize> = prefilter.candidates("ab axyz a", 0).collect();
+    assert_eq!(candidates, vec![0, 3]);
+    assert_eq!(prefilter.candidates("abcabc", 0).count(), 0);
+    let prefilter = Prefilter::from_regexp("(a|b)*c");
+    assert_eq!(prefilter.candidates("héc", 1).collect::<Vec<_>>(), vec![1, 3]);
+  }
+
+  #[test]
+  fn test_rejects_early() {
+    let matcher = PrefilteredMatcher::with_automaton("(a|b)*abb", NFAOne::from_regexp);
+    assert!(!matcher.prefilter.might_match("ababa"));
+    assert!(!matcher.test("ababa"));
+    assert!(matcher.test("babb"));
+  }
+}
@@ -0,0 +1,554 @@
+use super::char_class::{parse_property, CharClass};
+use super::dfa_regexp::NodeType;
+use super::escape_chars::MaybeEsc;
+use super::flags::{case_fold, case_variants, Flags};
+
+// char offsets into the parsed pattern, the end is exclusive
+pub type Span = (usize, usize);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GroupKind {
+  NonCapture,       // (?:..)
+  Capture(usize),   // (..), numbered by their "(" from 1
+  Look { behind: bool, negated: bool }, // (?=..) (?!..) (?<=..) (?<!..)
+}
+
+// a zero-width assertion about the char on one side of the position, ordered so that
+// of two end anchors the stricter one is the larger
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Anchor {
+  StartText, // ^
+  StartLine, // (?m:^), also right after a \n
+  EndLine,   // (?m:$), also right before a \n
+  EndText,   // $
+}
+
+// what an anchor can tell apart about the char next to a position
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Boundary {
+  Text, // no char, the start or end of the text
+  NewLine,
+  Other,
+}
+
+impl Boundary {
+  pub fn of(chr: Option<char>) -> Self {
+    match chr {
+      None => Boundary::Text,
+      Some('\n') => Boundary::NewLine,
+      Some(_) => Boundary::Other,
+    }
+  }
+}
+
+impl Anchor {
+  // ^ looks at the char before the position, $ at the one after it
+  pub fn looks_behind(self) -> bool {
+    matches!(self, Anchor::StartText | Anchor::StartLine)
+  }
+
+  // whether the anchor holds with `boundary` on the side it looks at
+  pub fn holds(self, boundary: Boundary) -> bool {
+    match self {
+      Anchor::StartText | Anchor::EndText => boundary == Boundary::Text,
+      Anchor::StartLine | Anchor::EndLine => boundary != Boundary::Other,
+    }
+  }
+
+  // the anchor holding at the same place in the reversed text
+  pub fn reverse(self) -> Anchor {
+    match self {
+      Anchor::StartText => Anchor::EndText,
+      Anchor::StartLine => Anchor::EndLine,
+      Anchor::EndLine => Anchor::StartLine,
+      Anchor::EndText => Anchor::StartText,
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AstKind {
+  Empty,
+  Char(char),
+  Class(CharClass),
+  Anchor(Anchor),
+  Backref(usize),
+  Group { kind: GroupKind, child: Box<Ast> },
+  Unary { node_type: NodeType, child: Box<Ast> },
+  // two or more operands of |, & or concatenation, flat so long patterns stay shallow
+  List { node_type: NodeType, children: Vec<Ast> },
+}
+
+// the syntax tree of a pattern before any engine rejects what it doesn't support
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ast {
+  pub kind: AstKind,
+  pub span: Span,
+  pub flags: Flags, // in effect where the node starts, only leaves depend on them
+}
+
+impl Ast {
+  // the chars a Char or Class leaf matches under its flags
+  pub fn leaf_class(&self) -> Option<CharClass> {
+    match &self.kind {
+      AstKind::Char(chr) if self.flags.case_insensitive => {
+        Some(CharClass::new(case_variants(*chr).into_iter().map(|chr| (chr, chr)).collect()))
+      },
+      AstKind::Char(chr) => Some(CharClass::single(*chr)),
+      AstKind::Class(class) if self.flags.case_insensitive => Some(case_fold(class)),
+      AstKind::Class(class) => Some(class.clone()),
+      _ => None,
+    }
+  }
+
+  // a char of every range of the leaves, and a second one where the range starts
+  // with \n. An edge built from a leaf is taken by one of them, and as the anchors
+  // only tell \n apart from the other chars, with the same effect.
+  pub fn representative_chars(&self) -> String {
+    let mut res = vec![];
+    self.collect_representatives(&mut res);
+    res.sort_unstable();
+    res.dedup();
+    res.into_iter().collect()
+  }
+
+  fn collect_representatives(&self, res: &mut Vec<char>) {
+    match &self.kind {
+      AstKind::Group { child, .. } | AstKind::Unary { child, .. } => child.collect_representatives(res),
+      AstKind::List { children, .. } => {
+        for child in children {
+          child.collect_representatives(res);
+        }
+      },
+      _ => {
+        for &(lo, hi) in self.leaf_class().iter().flat_map(|class| class.ranges()) {
+          res.push(lo);
+          if lo == '\n' && hi > lo {
+            res.push('\u{B}');
+          }
+        }
+      },
+    }
+  }
+}
+
+struct Parser {
+  chars: Vec<char>,
+  pos: usize,
+  flags: Flags,
+  groups: usize,
+  backrefs: Vec<(usize, usize)>, // where each backreference starts and its group
+}
+
+// Recursive descent, from the loosest binding operator to the tightest:
+//   union        := intersect ('|' intersect)*
+//   intersect    := concat ('&' concat)*
+//   concat       := complement+
+//   complement   := '~' complement | postfix
+//   postfix      := atom ('*' | '+' | '?' | '{m,n}')*
+// An inline flag group "(?flags)" is skipped like verbose mode whitespace and changes
+// the flags up to the end of the enclosing group, "(?flags:..)" only inside it.
+pub fn parse(reg_exp: &str) -> Ast {
+  parse_with_flags(reg_exp, Flags::default())
+}
+
+pub fn parse_with_flags(reg_exp: &str, flags: Flags) -> Ast {
+  let mut parser = Parser { chars: reg_exp.chars().collect(), pos: 0, flags, groups: 0, backrefs: vec![] };
+  let res = parser.parse_union();
+  if parser.pos < parser.chars.len() {
+    parser.error("unmatched )");
+  }
+  // a backreference may come before its group, so they are checked once all are counted
+  if let Some(&(pos, group)) = parser.backrefs.iter().find(|&&(_, group)| group > parser.groups) {
+    parser.pos = pos;
+    parser.error(&format!("backreference \\{} to a missing group", group));
+  }
+  res
+}
+
+impl Parser {
+  fn error(&self, msg: &str) -> ! {
+    panic!("parse error at {}: {}", self.pos, msg)
+  }
+
+  // the letters of a "(?flags)" or "(?flags:" starting at `from`, with the ")" or ":"
+  fn flag_group_at(&self, from: usize) -> Option<(usize, char)> {
+    if self.chars.get(from) != Some(&'(') || self.chars.get(from + 1) != Some(&'?') {
+      return None;
+    }
+    let letters = self.chars[from + 2..].iter().take_while(|c| c.is_ascii_alphabetic() || **c == '-').count();
+    match self.chars.get(from + 2 + letters) {
+      Some(&end @ (')' | ':')) if letters > 0 => Some((letters, end)),
+      _ => None,
+    }
+  }
+
+  // apply the flag letters after the "(?" at `self.pos` and move past them
+  fn parse_flags(&mut self, letters: usize) {
+    let mut on = true;
+    for idx in self.pos + 2..self.pos + 2 + letters {
+      let flags = match self.chars[idx] {
+        '-' if on => {
+          on = false;
+          continue;
+        },
+        flag => self.flags.set(flag, on),
+      };
+      self.flags = flags.unwrap_or_else(|| {
+        self.pos = idx;
+        self.error(&format!("unknown flag {}", self.chars[idx]))
+      });
+    }
+    self.pos += 2 + letters;
+  }
+
+  // move past verbose mode whitespace and comments and inline flag groups
+  fn skip_ignored(&mut self) {
+    loop {
+      match self.chars.get(self.pos) {
+        Some(chr) if self.flags.verbose && chr.is_whitespace() => self.pos += 1,
+        Some('#') if self.flags.verbose => {
+          while self.chars.get(self.pos).is_some_and(|&chr| chr != '\n') {
+            self.pos += 1;
+          }
+        },
+        _ => match self.flag_group_at(self.pos) {
+          Some((letters, ')')) => {
+            self.parse_flags(letters);
+            self.pos += 1;
+          },
+          _ => return,
+        },
+      }
+    }
+  }
+
+  // the next char with its escape, and how many chars it takes
+  fn peek_len(&mut self) -> Option<(MaybeEsc, usize)> {
+    self.skip_ignored();
+    match self.chars.get(self.pos)? {
+      '\\' => match self.chars.get(self.pos + 1) {
+        Some(&chr) => Some((MaybeEsc::Esc(chr), 2)),
+        None => self.error("pattern ends in an escape"),
+      },
+      &chr => Some((MaybeEsc::NonEsc(chr), 1)),
+    }
+  }
+
+  fn peek(&mut self) -> Option<MaybeEsc> {
+    self.peek_len().map(|(chr, _)| chr)
+  }
+
+  fn next(&mut self) -> Option<MaybeEsc> {
+    let (chr, len) = self.peek_len()?;
+    self.pos += len;
+    Some(chr)
+  }
+
+  fn eat(&mut self, chr: char) -> bool {
+    if self.peek() == Some(MaybeEsc::NonEsc(chr)) {
+      self.pos += 1;
+      return true;
+    }
+    false
+  }
+
+  fn expect(&mut self, chr: char, msg: &str) {
+    if !self.eat(chr) {
+      self.error(msg);
+    }
+  }
+
+  fn list(node_type: NodeType, mut children: Vec<Ast>) -> Ast {
+    if children.len() == 1 {
+      return children.pop().unwrap();
+    }
+    Ast {
+      span: (children[0].span.0, children.last().unwrap().span.1),
+      flags: children[0].flags,
+      kind: AstKind::List { node_type, children },
+    }
+  }
+
+  fn unary(node_type: NodeType, child: Ast, span: Span) -> Ast {
+    Ast { flags: child.flags, kind: AstKind::Unary { node_type, child: Box::new(child) }, span }
+  }
+
+  fn at_operand_end(&mut self) -> bool {
+    matches!(self.peek(), None | Some(MaybeEsc::NonEsc('|' | '&' | ')')))
+  }
+
+  fn parse_union(&mut self) -> Ast {
+    // only a whole empty pattern or group means ε, "a|" is missing its operand
+    if matches!(self.peek(), None | Some(MaybeEsc::NonEsc(')'))) {
+      return Ast { kind: AstKind::Empty, span: (self.pos, self.pos), flags: self.flags };
+    }
+    let mut children = vec![self.parse_intersect()];
+    while self.eat('|') {
+      children.push(self.parse_intersect());
+    }
+    Parser::list(NodeType::Union, children)
+  }
+
+  fn parse_intersect(&mut self) -> Ast {
+    let mut children = vec![self.parse_concat()];
+    while self.eat('&') {
+      children.push(self.parse_concat());
+    }
+    Parser::list(NodeType::Intersect, children)
+  }
+
+  fn parse_concat(&mut self) -> Ast {
+    if self.at_operand_end() {
+      self.error("missing operand");
+    }
+    let mut children = vec![self.parse_complement()];
+    while !self.at_operand_end() {
+      children.push(self.parse_complement());
+    }
+    Parser::list(NodeType::Concat, children)
+  }
+
+  fn parse_complement(&mut self) -> Ast {
+    self.skip_ignored();
+    let start = self.pos;
+    if self.eat('~') {
+      let child = self.parse_complement();
+      let end = child.span.1;
+      return Parser::unary(NodeType::Complement, child, (start, end));
+    }
+    self.parse_postfix()
+  }
+
+  fn parse_postfix(&mut self) -> Ast {
+    let start = self.pos;
+    let mut res = self.parse_atom();
+    loop {
+      let node_type = match self.peek() {
+        Some(MaybeEsc::NonEsc('*')) => NodeType::Closure,
+        Some(MaybeEsc::NonEsc('+')) => NodeType::Plus,
+        Some(MaybeEsc::NonEsc('?')) => NodeType::Question,
+        Some(MaybeEsc::NonEsc('{')) => {
+          self.pos += 1;
+          let (min, max) = self.parse_repeat();
+          res = Parser::unary(NodeType::Repeat { min, max }, res, (start, self.pos));
+          continue;
+        },
+        _ => return res,
+      };
+      self.pos += 1;
+      res = Parser::unary(node_type, res, (start, self.pos));
+    }
+  }
+
+  fn parse_atom(&mut self) -> Ast {
+    self.skip_ignored();
+    let start = self.pos;
+    let flags = self.flags;
+    let kind = match self.next() {
+      Some(MaybeEsc::NonEsc('(')) => {
+        let kind = if let Some((letters, ':')) = self.flag_group_at(start) {
+          self.pos = start;
+          self.parse_flags(letters);
+          self.pos += 1;
+          GroupKind::NonCapture
+        } else if self.chars.get(self.pos) == Some(&'?') {
+          self.pos += 1;
+          self.parse_group_kind()
+        } else {
+          self.groups += 1;
+          GroupKind::Capture(self.groups)
+        };
+        let child = self.parse_union();
+        if !self.eat(')') {
+          self.pos = start;
+          self.error("unclosed group");
+        }
+        self.flags = flags;
+        AstKind::Group { kind, child: Box::new(child) }
+      },
+      Some(MaybeEsc::NonEsc(op @ ('*' | '+' | '?' | '{'))) => {
+        self.pos = start;
+        self.error(&format!("{} is missing its operand", op));
+      },
+      Some(MaybeEsc::Esc(digit @ '1'..='9')) => {
+        let group = digit.to_digit(10).unwrap() as usize;
+        self.backrefs.push((start, group));
+        AstKind::Backref(group)
+      },
+      Some(MaybeEsc::Esc(p @ ('p' | 'P'))) => {
+        // the name is read as written, verbose mode doesn't apply inside it
+        let name: String = match self.chars.get(self.pos) {
+          Some('{') => match self.chars[self.pos..].iter().position(|&chr| chr == '}') {
+            Some(len) => self.chars[self.pos..=self.pos + len].iter().collect(),
+            None => {
+              self.pos = start;
+              self.error("unterminated property")
+            },
+          },
+          Some(&chr) => chr.to_string(),
+          None => self.error("missing property name"),
+        };
+        self.pos += name.chars().count();
+        AstKind::Class(parse_property(&format!("\\{}{}", p, name)))
+      },
+      Some(MaybeEsc::NonEsc('.')) if self.flags.dot_matches_new_line => {
+        AstKind::Class(CharClass::new(vec![('\0', char::MAX)]).with_name("(?s:.)".to_string()))
+      },
+      Some(MaybeEsc::NonEsc('.')) => {
+        AstKind::Class(CharClass::single('\n').negate().with_name(".".to_string()))
+      },
+      Some(MaybeEsc::NonEsc('^')) if self.flags.multi_line => AstKind::Anchor(Anchor::StartLine),
+      Some(MaybeEsc::NonEsc('^')) => AstKind::Anchor(Anchor::StartText),
+      Some(MaybeEsc::NonEsc('$')) if self.flags.multi_line => AstKind::Anchor(Anchor::EndLine),
+      Some(MaybeEsc::NonEsc('$')) => AstKind::Anchor(Anchor::EndText),
+      Some(chr) => AstKind::Char(chr.get_chr()),
+      None => self.error("missing operand"),
+    };
+    Ast { kind, span: (start, self.pos), flags }
+  }
+
+  // what follows "(?"
+  fn parse_group_kind(&mut self) -> GroupKind {
+    match self.next() {
+      Some(MaybeEsc::NonEsc(':')) => GroupKind::NonCapture,
+      Some(MaybeEsc::NonEsc('=')) => GroupKind::Look { behind: false, negated: false },
+      Some(MaybeEsc::NonEsc('!')) => GroupKind::Look { behind: false, negated: true },
+      Some(MaybeEsc::NonEsc('<')) => match self.next() {
+        Some(MaybeEsc::NonEsc('=')) => GroupKind::Look { behind: true, negated: false },
+        Some(MaybeEsc::NonEsc('!')) => GroupKind::Look { behind: true, negated: true },
+        _ => self.error("unknown group (?<"),
+      },
+      _ => self.error("unknown group (?"),
+    }
+  }
+
+  fn read_number(&mut self) -> Option<usize> {
+    let start = self.pos;
+    while matches!(self.chars.get(self.pos), Some('0'..='9')) {
+      self.pos += 1;
+    }
+    self.chars[start..self.pos].iter().collect::<String>().parse().ok()
+  }
+
+  // the "m}", "m,}" or "m,n}" after a "{"
+  fn parse_repeat(&mut self) -> (usize, Option<usize>) {
+    let min = self.read_number().unwrap_or_else(|| self.error("repetition needs a minimum"));
+    if self.eat('}') {
+      return (min, Some(min));
+    }
+    self.expect(',', "invalid repetition");
+    let max = self.read_number();
+    self.expect('}', "unterminated repetition");
+    if let Some(max) = max {
+      if min > max {
+        self.error(&format!("repetition {{{},{}}} is empty", min, max));
+      }
+    }
+    (min, max)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn shape(ast: &Ast) -> String {
+    match &ast.kind {
+      AstKind::Empty => "()".to_string(),
+      AstKind::Char(chr) => chr.to_string(),
+      AstKind::Class(class) => class.name().unwrap().to_string(),
+      AstKind::Anchor(anchor) => format!("{:?}", anchor),
+      AstKind::Backref(group) => format!("\\{}", group),
+      AstKind::Group { kind, child } => format!("{:?}[{}]", kind, shape(child)),
+      AstKind::Unary { node_type, child } => format!("{:?}[{}]", node_type, shape(child)),
+      AstKind::List { node_type, children } => {
+        format!("{:?}[{}]", node_type, children.iter().map(shape).collect::<Vec<_>>().join(","))
+      },
+    }
+  }
+
+  #[test]
+  fn precedence() {
+    assert_eq!(shape(&parse("a|b&cd")), "Union[a,Intersect[b,Concat[c,d]]]");
+    assert_eq!(shape(&parse("abc")), "Concat[a,b,c]");
+    assert_eq!(shape(&parse("~ab*")), "Concat[Complement[a],Closure[b]]");
+    assert_eq!(shape(&parse("~a*")), "Complement[Closure[a]]");
+    assert_eq!(shape(&parse("a*?{2}")), "Repeat { min: 2, max: Some(2) }[Question[Closure[a]]]");
+    assert_eq!(shape(&parse("(a)(?:b)(?<!c)\\1")), "Concat[Capture(1)[a],NonCapture[b],Look { behind: true, negated: true }[c],\\1]");
+    assert_eq!(shape(&parse("\\pL\\p{Greek}\\*()")), "Concat[\\pL,\\p{Greek},*,Capture(1)[()]]");
+    assert_eq!(shape(&parse("")), "()");
+    assert_eq!(shape(&parse("^.\\.(?m)$")), "Concat[StartText,.,.,EndLine]");
+  }
+
+  #[test]
+  fn spans() {
+    let ast = parse("x(ab)*|\\p{L}c{2,3}");
+    assert_eq!(ast.span, (0, 18));
+    let children = |ast: &Ast| match &ast.kind {
+      AstKind::List { children, .. } => children.iter().map(|child| child.span).collect::<Vec<_>>(),
+      other => panic!("{:?}", other),
+    };
+    assert_eq!(children(&ast), vec![(0, 6), (7, 18)]);
+    if let AstKind::List { children: alternatives, .. } = &ast.kind {
+      assert_eq!(children(&alternatives[0]), vec![(0, 1), (1, 6)]);
+      assert_eq!(children(&alternatives[1]), vec![(7, 12), (12, 18)]);
+    }
+  }
+
+  #[test]
+  fn flags() {
+    let ast = parse_with_flags("a b # c\n (?-x: d)", Flags::default().verbose(true));
+    assert_eq!(shape(&ast), "Concat[a,b,NonCapture[Concat[ ,d]]]");
+    assert_eq!(ast.span, (0, 17));
+    let ast = parse("a(?i:b(?-i)c)d(?i)e");
+    let leaves = |ast: &Ast| match &ast.kind {
+      AstKind::List { children, .. } => children.iter().map(|child| child.flags.case_insensitive).collect::<Vec<_>>(),
+      other => panic!("{:?}", other),
+    };
+    assert_eq!(leaves(&ast), vec![false, false, false, true]);
+    if let AstKind::List { children, .. } = &ast.kind {
+      let AstKind::Group { child, .. } = &children[1].kind else { panic!() };
+      assert_eq!(leaves(child), vec![true, false]);
+    }
+  }
+
+  #[test]
+  fn error_offsets_through_from_regexp() {
+    use super::super::nfa::NFAOne;
+    use std::panic::catch_unwind;
+    let message = |reg_exp: &'static str| {
+      let err = catch_unwind(|| NFAOne::from_regexp(reg_exp)).err().unwrap();
+      err.downcast_ref::<String>().unwrap().clone()
+    };
+    assert_eq!(message("a.b|"), "parse error at 4: missing operand");
+    assert_eq!(message("(?i)ab|"), "parse error at 7: missing operand");
+    assert_eq!(message("(?x) a | "), "parse error at 9: missing operand");
+    assert_eq!(message("a(?iq)"), "parse error at 4: unknown flag q");
+    assert_eq!(message("\\\\(a"), "parse error at 2: unclosed group");
+  }
+
+  #[test]
+  #[should_panic(expected = "parse error at 3: missing operand")]
+  fn error_missing_operand() {
+    parse("ab|");
+  }
+
+  #[test]
+  #[should_panic(expected = "parse error at 1: unclosed group")]
+  fn error_unclosed_group() {
+    parse("a(b|c");
+  }
+
+  #[test]
+  #[should_panic(expected = "parse error at 2: unmatched )")]
+  fn error_unmatched_paren() {
+    parse("ab)c");
+  }
+
+  #[test]
+  #[should_panic(expected = "parse error at 1: * is missing its operand")]
+  fn error_postfix_without_operand() {
+    parse("(*a)");
+  }
+}
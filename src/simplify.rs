@@ -0,0 +1,282 @@
+use super::char_class::CharClass;
+use super::derivative::nullable;
+use super::dfa_regexp::{NodeType, RegASTNode};
+use std::fmt;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SimplifyReport {
+  pub nodes_before: usize,
+  pub nodes_after: usize,
+}
+
+impl fmt::Display for SimplifyReport {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{} nodes -> {} nodes", self.nodes_before, self.nodes_after)
+  }
+}
+
+pub fn node_count(node: &RegASTNode) -> usize {
+  match node {
+    RegASTNode::Unary { child, .. } => 1 + node_count(child),
+    RegASTNode::Binary { left_child, right_child, .. } => 1 + node_count(left_child) + node_count(right_child),
+    _ => 1,
+  }
+}
+
+// rewrites `node` into a smaller tree of the same language: single char alternatives
+// become one class, alternatives sharing their first factor get it factored out,
+// nested stars collapse and ε factors of concatenations disappear
+pub fn simplify(node: &RegASTNode) -> (RegASTNode, SimplifyReport) {
+  // a root end marker stays a factor of its own, even when the rest turns into ε
+  let res = match node {
+    RegASTNode::Binary { node_type: NodeType::Concat, left_child, right_child }
+      if **right_child == RegASTNode::Endmarker => RegASTNode::Binary {
+      node_type: NodeType::Concat,
+      left_child: Box::new(simplify_node(left_child)),
+      right_child: right_child.clone(),
+    },
+    _ => simplify_node(node),
+  };
+  let report = SimplifyReport {
+    nodes_before: node_count(node),
+    nodes_after: node_count(&res),
+  };
+  (res, report)
+}
+
+fn simplify_node(node: &RegASTNode) -> RegASTNode {
+  match node {
+    RegASTNode::Unary { node_type, child } => mk_unary(*node_type, simplify_node(child)),
+    RegASTNode::Binary { node_type: NodeType::Concat, .. } => {
+      let mut factors = vec![];
+      collect(NodeType::Concat, node, &mut factors, true);
+      mk_concat(factors)
+    },
+    RegASTNode::Binary { node_type: NodeType::Union, .. } => {
+      let mut alternatives = vec![];
+      collect(NodeType::Union, node, &mut alternatives, true);
+      mk_union(alternatives)
+    },
+    RegASTNode::Binary { node_type, left_child, right_child } => RegASTNode::Binary {
+      node_type: *node_type,
+      left_child: Box::new(simplify_node(left_child)),
+      right_child: Box::new(simplify_node(right_child)),
+    },
+    _ => node.clone(),
+  }
+}
+
+// the operands of a chain of `node_type` nodes, simplified first when `simplify` is set
+fn collect(node_type: NodeType, node: &RegASTNode, res: &mut Vec<RegASTNode>, simplify: bool) {
+  match node {
+    RegASTNode::Binary { node_type: nt, left_child, right_child } if *nt == node_type => {
+      collect(node_type, left_child, res, simplify);
+      collect(node_type, right_child, res, simplify);
+    },
+    _ if simplify => collect(node_type, &simplify_node(node), res, false),
+    _ => res.push(node.clone()),
+  }
+}
+
+fn mk_binary_chain(node_type: NodeType, operands: Vec<RegASTNode>) -> Option<RegASTNode> {
+  operands.into_iter().reduce(|left, right| RegASTNode::Binary {
+    node_type,
+    left_child: Box::new(left),
+    right_child: Box::new(right),
+  })
+}
+
+fn mk_unary(node_type: NodeType, child: RegASTNode) -> RegASTNode {
+  let unary = |node_type, child| RegASTNode::Unary { node_type, child: Box::new(child) };
+  match (node_type, child) {
+    (NodeType::Repeat { min: 1, max: Some(1) }, child) => child,
+    (NodeType::Repeat { min: 0, max: Some(0) }, _) => RegASTNode::LeafEmpty,
+    (NodeType::Repeat { min: 0, max: None }, child) => mk_unary(NodeType::Closure, child),
+    (NodeType::Repeat { min: 1, max: None }, child) => mk_unary(NodeType::Plus, child),
+    (NodeType::Repeat { min: 0, max: Some(1) }, child) => mk_unary(NodeType::Question, child),
+    (NodeType::Complement, RegASTNode::Unary { node_type: NodeType::Complement, child }) => *child,
+    (NodeType::Complement, child) => unary(NodeType::Complement, child),
+    (NodeType::Repeat { .. }, RegASTNode::LeafEmpty) => RegASTNode::LeafEmpty,
+    (node_type @ NodeType::Repeat { .. }, child) => unary(node_type, child),
+
+    // the remaining ones are *, + and ?
+    (_, RegASTNode::LeafEmpty) => RegASTNode::LeafEmpty,
+    (NodeType::Plus, RegASTNode::LeafEmptySet) => RegASTNode::LeafEmptySet,
+    (_, RegASTNode::LeafEmptySet) => RegASTNode::LeafEmpty,
+    (node_type, RegASTNode::Unary { node_type: inner, child }) if is_star_like(inner) => {
+      if node_type == inner {
+        unary(node_type, *child)
+      } else {
+        unary(NodeType::Closure, *child) // any two different ones of *, + and ? make a *
+      }
+    },
+    (NodeType::Question, child) if nullable(&child) => child,
+    (node_type, child) => unary(node_type, child),
+  }
+}
+
+fn is_star_like(node_type: NodeType) -> bool {
+  matches!(node_type, NodeType::Closure | NodeType::Plus | NodeType::Question)
+}
+
+fn mk_concat(factors: Vec<RegASTNode>) -> RegASTNode {
+  if factors.contains(&RegASTNode::LeafEmptySet) {
+    return RegASTNode::LeafEmptySet;
+  }
+  let mut flat = vec![];
+  for factor in factors.iter().filter(|f| **f != RegASTNode::LeafEmpty) {
+    collect(NodeType::Concat, factor, &mut flat, false);
+  }
+  mk_binary_chain(NodeType::Concat, flat).unwrap_or(RegASTNode::LeafEmpty)
+}
+
+fn char_class(node: &RegASTNode) -> Option<CharClass> {
+  match node {
+    RegASTNode::Leaf(chr) => Some(CharClass::single(*chr)),
+    RegASTNode::Class(class) => Some(class.clone()),
+    _ => None,
+  }
+}
+
+fn mk_union(alternatives: Vec<RegASTNode>) -> RegASTNode {
+  let mut unique: Vec<RegASTNode> = vec![];
+  for alternative in alternatives {
+    if alternative != RegASTNode::LeafEmptySet && !unique.contains(&alternative) {
+      unique.push(alternative);
+    }
+  }
+
+  // alternatives with the same first factor, in order of first appearance
+  let mut groups: Vec<(RegASTNode, Vec<Vec<RegASTNode>>)> = vec![];
+  for alternative in unique {
+    let mut factors = vec![];
+    collect(NodeType::Concat, &alternative, &mut factors, false);
+    let head = factors.remove(0);
+    match groups.iter_mut().find(|(h, _)| *h == head) {
+      Some((_, tails)) => tails.push(factors),
+      None => groups.push((head, vec![factors])),
+    }
+  }
+  let factored: Vec<RegASTNode> = groups
+    .into_iter()
+    .map(|(head, tails)| {
+      if tails.len() == 1 {
+        let mut factors = tails.into_iter().next().unwrap();
+        factors.insert(0, head);
+        return mk_concat(factors);
+      }
+      let tail = mk_union(tails.into_iter().map(mk_concat).collect());
+      mk_concat(vec![head, tail])
+    })
+    .collect();
+
+  // single chars and classes merge into the first of them
+  let mut merged: Option<(usize, CharClass)> = None;
+  let mut res = vec![];
+  let mut merged_count = 0;
+  for alternative in factored {
+    match (char_class(&alternative), &mut merged) {
+      (Some(class), Some((_, acc))) => {
+        *acc = acc.union(&class);
+        merged_count += 1;
+      },
+      (Some(class), None) => {
+        merged = Some((res.len(), class));
+        merged_count = 1;
+        res.push(alternative);
+      },
+      (None, _) => res.push(alternative),
+    }
+  }
+  if let (Some((idx, class)), true) = (merged, merged_count > 1) {
+    res[idx] = match class.as_single() {
+      Some(chr) => RegASTNode::Leaf(chr),
+      None => RegASTNode::Class(class),
+    };
+  }
+
+  // ε is redundant next to a nullable alternative, otherwise it becomes a ?
+  if res.len() > 1 && res.contains(&RegASTNode::LeafEmpty) {
+    res.retain(|node| *node != RegASTNode::LeafEmpty);
+    if !res.iter().any(nullable) {
+      let rest = mk_binary_chain(NodeType::Union, res).unwrap();
+      return mk_unary(NodeType::Question, rest);
+    }
+  }
+  mk_binary_chain(NodeType::Union, res).unwrap_or(RegASTNode::LeafEmptySet)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::automaton::Automaton;
+  use super::super::dfa::DFAOne;
+  use super::super::dfa_regexp::{parse_ast_regexp, PositionAutomaton};
+  use super::super::equivalence::equivalent;
+
+  fn simplified(reg_exp: &str) -> String {
+    simplify(&parse_ast_regexp(reg_exp)).0.to_string()
+  }
+
+  #[test]
+  fn rewrites() {
+    assert_eq!(simplified("(a*)*"), "a*");
+    assert_eq!(simplified("((a+)?)*b??"), "a*b?");
+    assert_eq!(simplified("a()b(())*"), "ab");
+    assert_eq!(simplified("abc|abd|e"), "ab\\p{U+63-U+64}|e");
+    assert_eq!(simplified("if|in|int"), "i(f|nt?)");
+    assert_eq!(simplified("a|()|b*"), "a|b*");
+    assert_eq!(simplified("(a|b){1}|a{0,}"), "\\p{U+61-U+62}|a*");
+    assert_eq!(simplified("~~a"), "a");
+  }
+
+  #[test]
+  fn nullable_only() {
+    for reg_exp in ["()", "()*", "(()|())+"] {
+      let (res, _) = simplify(&parse_ast_regexp(reg_exp));
+      assert_eq!(res.to_string(), "()");
+      let dfa = DFAOne::from_regexp_ast(&res, "a");
+      assert!(dfa.test(""));
+      assert!(!dfa.test("a"));
+    }
+  }
+
+  #[test]
+  fn merges_char_alternatives() {
+    let (ast, report) = simplify(&parse_ast_regexp("(0|1|2|3|4|5|6|7|8|9)+"));
+    assert_eq!(report, SimplifyReport { nodes_before: 22, nodes_after: 4 });
+    assert_eq!(report.to_string(), "22 nodes -> 4 nodes");
+    match ast {
+      RegASTNode::Binary { left_child, .. } => match *left_child {
+        RegASTNode::Unary { node_type: NodeType::Plus, child } => {
+          assert_eq!(*child, RegASTNode::Class(CharClass::new(vec![('0', '9')])));
+        },
+        other => panic!("{:?}", other),
+      },
+      other => panic!("{:?}", other),
+    }
+
+    let num_exp = parse_ast_regexp("((1|2|3|4|5|6|7|8|9)(0|1|2|3|4|5|6|7|8|9)*|0)(\\.(0|1|2|3|4|5|6|7|8|9)+)?");
+    assert_eq!(PositionAutomaton::from_ast(&num_exp).states_size(), 32);
+    assert_eq!(PositionAutomaton::from_ast(&simplify(&num_exp).0).states_size(), 6);
+  }
+
+  #[test]
+  fn same_language() {
+    for reg_exp in [
+      "abc|abd|ab",
+      "(a|b|ab|ba)*c",
+      "((a*)*|b+)?(b|a|())",
+      "a{2,3}|a{0,1}b",
+      "(ab|ac)*&~(a|())",
+      "(a|b)*abb|(a|b)*aba",
+    ] {
+      let ast = parse_ast_regexp(reg_exp);
+      let (res, report) = simplify(&ast);
+      assert!(report.nodes_after <= report.nodes_before, "{}", reg_exp);
+      let before = DFAOne::from_regexp_ast(&ast, "abc");
+      let after = DFAOne::from_regexp_ast(&res, "abc");
+      assert_eq!(equivalent(&before, &after, "abc"), Ok(()), "{} became {}", reg_exp, res);
+    }
+  }
+}
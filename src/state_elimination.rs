@@ -0,0 +1,226 @@
+use super::dfa::DFAOne;
+use super::dfa_regexp::{NodeType, RegASTNode};
+use super::nfa::NFAOne;
+use super::simplify::{node_count, simplify};
+use std::collections::{HashMap, HashSet};
+
+fn union(left: RegASTNode, right: RegASTNode) -> RegASTNode {
+  match (left, right) {
+    (RegASTNode::LeafEmptySet, other) | (other, RegASTNode::LeafEmptySet) => other,
+    (left, right) if left == right => left,
+    (left, right) => RegASTNode::Binary {
+      node_type: NodeType::Union,
+      left_child: Box::new(left),
+      right_child: Box::new(right),
+    },
+  }
+}
+
+fn concat(left: RegASTNode, right: RegASTNode) -> RegASTNode {
+  match (left, right) {
+    (RegASTNode::LeafEmptySet, _) | (_, RegASTNode::LeafEmptySet) => RegASTNode::LeafEmptySet,
+    (RegASTNode::LeafEmpty, other) | (other, RegASTNode::LeafEmpty) => other,
+    (left, right) => RegASTNode::Binary {
+      node_type: NodeType::Concat,
+      left_child: Box::new(left),
+      right_child: Box::new(right),
+    },
+  }
+}
+
+fn closure(node: RegASTNode) -> RegASTNode {
+  match node {
+    RegASTNode::LeafEmpty | RegASTNode::LeafEmptySet => RegASTNode::LeafEmpty,
+    RegASTNode::Unary { node_type: NodeType::Closure, .. } => node,
+    _ => RegASTNode::Unary { node_type: NodeType::Closure, child: Box::new(node) },
+  }
+}
+
+// generalized NFA (GNFA) whose edges are labeled by regular expressions, it has a single
+// start without incoming edges and a single accept without outgoing ones
+struct Gnfa {
+  out_edges: Vec<HashMap<usize, RegASTNode>>,
+  in_edges: Vec<HashSet<usize>>,
+  start: usize,
+  accept: usize,
+}
+
+impl Gnfa {
+  // `states_size` automaton states, the start and accept of the GNFA are added after them
+  fn new(states_size: usize) -> Self {
+    Gnfa {
+      out_edges: vec![HashMap::new(); states_size + 2],
+      in_edges: vec![HashSet::new(); states_size + 2],
+      start: states_size,
+      accept: states_size + 1,
+    }
+  }
+
+  fn add_edge(&mut self, from: usize, to: usize, label: RegASTNode) {
+    let old = self.out_edges[from].remove(&to).unwrap_or(RegASTNode::LeafEmptySet);
+    self.out_edges[from].insert(to, union(old, label));
+    self.in_edges[to].insert(from);
+  }
+
+  fn remove_state(&mut self, state: usize) {
+    for to in std::mem::take(&mut self.out_edges[state]).into_keys() {
+      self.in_edges[to].remove(&state);
+    }
+    for from in std::mem::take(&mut self.in_edges[state]) {
+      self.out_edges[from].remove(&state);
+    }
+  }
+
+  // states on no path from the start to the accept only add dead branches
+  fn prune(&mut self) {
+    let reach = |from: usize, next: &dyn Fn(usize) -> Vec<usize>| {
+      let mut seen = HashSet::new();
+      let mut stack = vec![from];
+      while let Some(state) = stack.pop() {
+        if seen.insert(state) {
+          stack.extend(next(state));
+        }
+      }
+      seen
+    };
+    let forward = reach(self.start, &|s| self.out_edges[s].keys().copied().collect());
+    let backward = reach(self.accept, &|s| self.in_edges[s].iter().copied().collect());
+    for state in 0..self.out_edges.len() {
+      if !forward.contains(&state) || !backward.contains(&state) {
+        self.remove_state(state);
+      }
+    }
+  }
+
+  // how much eliminating `state` adds: every in edge gets combined with every out edge
+  fn elimination_cost(&self, state: usize) -> usize {
+    let loop_size = self.out_edges[state].get(&state).map_or(0, node_count);
+    let ins: Vec<usize> = self.in_edges[state].iter().filter(|&&s| s != state).copied().collect();
+    let outs: Vec<usize> = self.out_edges[state].keys().filter(|&&s| s != state).copied().collect();
+    let in_size: usize = ins.iter().map(|from| node_count(&self.out_edges[*from][&state])).sum();
+    let out_size: usize = outs.iter().map(|to| node_count(&self.out_edges[state][to])).sum();
+    in_size * outs.len() + out_size * ins.len() + loop_size * ins.len() * outs.len()
+  }
+
+  fn eliminate(&mut self, state: usize) {
+    let loop_label = closure(self.out_edges[state].get(&state).cloned().unwrap_or(RegASTNode::LeafEmptySet));
+    let ins: Vec<(usize, RegASTNode)> = self.in_edges[state]
+      .iter()
+      .filter(|&&from| from != state)
+      .map(|&from| (from, self.out_edges[from][&state].clone()))
+      .collect();
+    let outs: Vec<(usize, RegASTNode)> = self.out_edges[state]
+      .iter()
+      .filter(|(&to, _)| to != state)
+      .map(|(&to, label)| (to, label.clone()))
+      .collect();
+    self.remove_state(state);
+    for (from, in_label) in &ins {
+      for (to, out_label) in &outs {
+        let label = concat(concat(in_label.clone(), loop_label.clone()), out_label.clone());
+        self.add_edge(*from, *to, label);
+      }
+    }
+  }
+
+  fn into_regex(mut self) -> RegASTNode {
+    self.prune();
+    let mut remaining: Vec<usize> = (0..self.start)
+      .filter(|&s| !self.in_edges[s].is_empty() || !self.out_edges[s].is_empty())
+      .collect();
+    while !remaining.is_empty() {
+      let (idx, _) = remaining
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, &s)| (self.elimination_cost(s), s))
+        .unwrap();
+      let state = remaining.swap_remove(idx);
+      self.eliminate(state);
+    }
+    let res = self.out_edges[self.start].remove(&self.accept).unwrap_or(RegASTNode::LeafEmptySet);
+    simplify(&res).0
+  }
+}
+
+impl DFAOne {
+  // a regular expression of the accepted language over the chars of `input`
+  pub fn to_regex(&self, input: &str) -> RegASTNode {
+    let mut gnfa = Gnfa::new(self.states_size);
+    if let Some(start) = self.start {
+      gnfa.add_edge(gnfa.start, start, RegASTNode::LeafEmpty);
+    }
+    for &state in &self.accept {
+      gnfa.add_edge(state, gnfa.accept, RegASTNode::LeafEmpty);
+    }
+    for state in 0..self.states_size {
+      for chr in input.chars() {
+        if let Some(to) = (self.transition_func)(state, chr) {
+          gnfa.add_edge(state, to, RegASTNode::Leaf(chr));
+        }
+      }
+    }
+    gnfa.into_regex()
+  }
+}
+
+impl NFAOne {
+  pub fn to_regex(&self, input: &str) -> RegASTNode {
+    let mut gnfa = Gnfa::new(self.states_size);
+    gnfa.add_edge(gnfa.start, self.start, RegASTNode::LeafEmpty);
+    for &state in &self.accept {
+      gnfa.add_edge(state, gnfa.accept, RegASTNode::LeafEmpty);
+    }
+    for state in 0..self.states_size {
+      for to in (self.transition_func)(state, None) {
+        gnfa.add_edge(state, to, RegASTNode::LeafEmpty);
+      }
+      for chr in input.chars() {
+        for to in (self.transition_func)(state, Some(chr)) {
+          gnfa.add_edge(state, to, RegASTNode::Leaf(chr));
+        }
+      }
+    }
+    gnfa.into_regex()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::equivalence::equivalent;
+  use super::super::language_ops;
+
+  fn round_trip(reg_exp: &str, input: &str) {
+    let dfa = DFAOne::from_regexp(reg_exp, input);
+    let from_dfa = dfa.to_regex(input);
+    let from_nfa = NFAOne::from_regexp(reg_exp).to_regex(input);
+    for res in [from_dfa, from_nfa] {
+      let res_dfa = DFAOne::from_regexp_ast(&res, input);
+      assert_eq!(equivalent(&dfa, &res_dfa, input), Ok(()), "{} became {}", reg_exp, res);
+    }
+  }
+
+  #[test]
+  fn to_regex_works() {
+    assert!(DFAOne::from_regexp("(a|b)*abb", "ab").state_minimization("ab").to_regex("ab").to_string().len() < 40);
+    assert_eq!(DFAOne::from_regexp("abc", "abc").to_regex("abc").to_string(), "abc");
+    assert_eq!(DFAOne::from_regexp("a*", "a").to_regex("a").to_string(), "a*");
+    assert_eq!(NFAOne::from_regexp("a|b").to_regex("ab"), RegASTNode::Class(super::super::char_class::CharClass::new(vec![('a', 'b')])));
+    assert_eq!(DFAOne::from_regexp("a&b", "ab").to_regex("ab"), RegASTNode::LeafEmptySet);
+    round_trip("(a|b)*abb", "ab");
+    round_trip("((1|2)(0|1|2)*|0)(.(0|1|2)+)?", "012.");
+    round_trip("(ab|ba)*|a(a|b)?", "ab");
+    round_trip("", "ab");
+  }
+
+  #[test]
+  fn to_regex_after_intersection() {
+    // even number of a and b at the same time
+    let even_a = DFAOne::from_regexp("(b|ab*a)*", "ab");
+    let even_b = DFAOne::from_regexp("(a|ba*b)*", "ab");
+    let both = language_ops::intersection(&even_a, &even_b, "ab");
+    let res = both.to_regex("ab");
+    let res_dfa = DFAOne::from_regexp_ast(&res, "ab");
+    assert_eq!(equivalent(&both, &res_dfa, "ab"), Ok(()), "{}", res);
+  }
+}
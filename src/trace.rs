@@ -0,0 +1,256 @@
+use super::automaton::{Automaton, AutomatonRunner};
+use super::dfa::DFAOne;
+use super::nfa::NFAOne;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+// what a trace needs to know about an automaton beyond running it
+pub trait Traceable: Automaton {
+  // the numbered states `s` stands for
+  fn state_ids(&self, s: &Self::State) -> Vec<usize>;
+  // the states entered before any e-closure, at the start and after reading `chr` in `s`
+  fn start_ids(&self) -> Vec<usize>;
+  fn moved_ids(&self, s: &Self::State, chr: char) -> Vec<usize>;
+  fn accept_ids(&self) -> Vec<usize>;
+  // every transition over the chars of `input`, None labels an e edge
+  fn edges(&self, input: &str) -> Vec<(usize, Option<char>, usize)>;
+}
+
+impl Traceable for NFAOne {
+  fn state_ids(&self, s: &Self::State) -> Vec<usize> {
+    s.clone()
+  }
+  fn start_ids(&self) -> Vec<usize> {
+    vec![self.start]
+  }
+  fn moved_ids(&self, s: &Self::State, chr: char) -> Vec<usize> {
+    self.transition(s, chr)
+  }
+  fn accept_ids(&self) -> Vec<usize> {
+    self.accept.clone()
+  }
+  fn edges(&self, input: &str) -> Vec<(usize, Option<char>, usize)> {
+    let mut res = vec![];
+    for from in 0..self.states_size {
+      for by in std::iter::once(None).chain(input.chars().map(Some)) {
+        res.extend((self.transition_func)(from, by).into_iter().map(|to| (from, by, to)));
+      }
+    }
+    res
+  }
+}
+
+impl Traceable for DFAOne {
+  fn state_ids(&self, s: &Self::State) -> Vec<usize> {
+    s.iter().copied().collect()
+  }
+  fn start_ids(&self) -> Vec<usize> {
+    self.start.iter().copied().collect()
+  }
+  fn moved_ids(&self, s: &Self::State, chr: char) -> Vec<usize> {
+    self.state_ids(&Automaton::transition(self, s, chr))
+  }
+  fn accept_ids(&self) -> Vec<usize> {
+    self.accept.clone()
+  }
+  fn edges(&self, input: &str) -> Vec<(usize, Option<char>, usize)> {
+    let mut res = vec![];
+    for from in 0..self.states_size {
+      for chr in input.chars() {
+        res.extend((self.transition_func)(from, chr).map(|to| (from, Some(chr), to)));
+      }
+    }
+    res
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceStep {
+  pub input: Option<char>, // None for the start
+  pub moved: Vec<usize>,   // the states the input led to
+  pub states: Vec<usize>,  // after the e-closure
+  pub is_accept: bool,
+  pub is_dead: bool,
+}
+
+impl TraceStep {
+  // the states only the e-closure added
+  pub fn closure_added(&self) -> Vec<usize> {
+    self.states.iter().copied().filter(|s| !self.moved.contains(s)).collect()
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Trace {
+  pub steps: Vec<TraceStep>,
+  pub rest: String, // input left unread once the automaton died
+}
+
+fn sorted(mut ids: Vec<usize>) -> Vec<usize> {
+  ids.sort_unstable();
+  ids.dedup();
+  ids
+}
+
+// run `automaton` on `s` and record every step, stops at the first dead state
+pub fn trace<A: Traceable>(automaton: &A, s: &str) -> Trace {
+  let mut runner = AutomatonRunner::new(automaton);
+  let step = |runner: &AutomatonRunner<&A>, input, moved| TraceStep {
+    input,
+    moved: sorted(moved),
+    states: sorted(automaton.state_ids(runner.state())),
+    is_accept: runner.is_accept(),
+    is_dead: runner.is_dead(),
+  };
+  let mut steps = vec![step(&runner, None, automaton.start_ids())];
+  for (idx, chr) in s.char_indices() {
+    if runner.is_dead() {
+      return Trace { steps, rest: s[idx..].to_string() };
+    }
+    let moved = automaton.moved_ids(runner.state(), chr);
+    runner.feed_char(chr);
+    steps.push(step(&runner, Some(chr), moved));
+  }
+  Trace { steps, rest: String::new() }
+}
+
+fn fmt_set(ids: &[usize]) -> String {
+  format!("{{{}}}", ids.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(", "))
+}
+
+fn yes_no(flag: bool) -> &'static str {
+  if flag { "yes" } else { "no" }
+}
+
+fn dot_label(chr: Option<char>) -> String {
+  match chr {
+    None => "ε".to_string(),
+    Some(chr @ ('"' | '\\')) => format!("\\{}", chr),
+    Some(chr) => chr.escape_debug().to_string(),
+  }
+}
+
+impl Trace {
+  // one row per step, the columns padded to line up
+  pub fn to_table(&self) -> String {
+    let mut rows = vec![["step", "input", "moved", "e-closure adds", "states", "accept", "dead"].map(String::from)];
+    for (idx, step) in self.steps.iter().enumerate() {
+      rows.push([
+        idx.to_string(),
+        step.input.map_or(String::new(), |chr| format!("{:?}", chr)),
+        fmt_set(&step.moved),
+        fmt_set(&step.closure_added()),
+        fmt_set(&step.states),
+        yes_no(step.is_accept).to_string(),
+        yes_no(step.is_dead).to_string(),
+      ]);
+    }
+    let widths: Vec<usize> = (0..7).map(|col| rows.iter().map(|row| row[col].chars().count()).max().unwrap()).collect();
+    let mut res = String::new();
+    for row in &rows {
+      let cells: Vec<String> = row.iter().zip(&widths).map(|(cell, &width)| format!("{:width$}", cell, width = width)).collect();
+      writeln!(res, "{}", cells.join(" | ").trim_end()).unwrap();
+    }
+    if !self.rest.is_empty() {
+      writeln!(res, "unread: {:?}", self.rest).unwrap();
+    }
+    res
+  }
+
+  // one digraph per step, the current states filled and the states the input
+  // led to drawn bold. `input` is the alphabet whose edges are drawn.
+  pub fn to_dot_frames<A: Traceable>(&self, automaton: &A, input: &str) -> Vec<String> {
+    // chars sharing the same source and target go on one edge
+    let mut edges: BTreeMap<(usize, usize), Vec<String>> = BTreeMap::new();
+    for (from, by, to) in automaton.edges(input) {
+      let labels = edges.entry((from, to)).or_default();
+      let label = dot_label(by);
+      if !labels.contains(&label) {
+        labels.push(label);
+      }
+    }
+    let accept = sorted(automaton.accept_ids());
+
+    self.steps.iter().enumerate().map(|(idx, step)| {
+      let mut res = String::new();
+      writeln!(res, "digraph step_{} {{", idx).unwrap();
+      writeln!(res, "  rankdir=LR;").unwrap();
+      let caption = match step.input {
+        None => "start".to_string(),
+        Some(chr) => format!("read {}", dot_label(Some(chr))),
+      };
+      writeln!(res, "  label=\"step {}: {}\";", idx, caption).unwrap();
+      writeln!(res, "  node [shape=circle];").unwrap();
+      for &s in &accept {
+        writeln!(res, "  {} [shape=doublecircle];", s).unwrap();
+      }
+      for &s in &step.states {
+        let bold = if step.moved.contains(&s) { ", penwidth=2" } else { "" };
+        writeln!(res, "  {} [style=filled, fillcolor=lightblue{}];", s, bold).unwrap();
+      }
+      for ((from, to), labels) in &edges {
+        writeln!(res, "  {} -> {} [label=\"{}\"];", from, to, labels.join(",")).unwrap();
+      }
+      res.push_str("}\n");
+      res
+    }).collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn nfa_trace() {
+    // states 0 -a-> 1 -e-> 2 -b-> 3
+    let nfa = NFAOne::from_regexp("ab");
+    let res = trace(&nfa, "ab");
+    assert_eq!(res.steps[1], TraceStep {
+      input: Some('a'),
+      moved: vec![1],
+      states: vec![1, 2],
+      is_accept: false,
+      is_dead: false,
+    });
+    assert_eq!(res.steps[1].closure_added(), vec![2]);
+    assert_eq!(res.to_table(), "\
+step | input | moved | e-closure adds | states | accept | dead
+0    |       | {0}   | {}             | {0}    | no     | no
+1    | 'a'   | {1}   | {2}            | {1, 2} | no     | no
+2    | 'b'   | {3}   | {}             | {3}    | yes    | no
+");
+
+    let frames = res.to_dot_frames(&nfa, "ab");
+    assert_eq!(frames.len(), 3);
+    assert_eq!(frames[1], "\
+digraph step_1 {
+  rankdir=LR;
+  label=\"step 1: read a\";
+  node [shape=circle];
+  3 [shape=doublecircle];
+  1 [style=filled, fillcolor=lightblue, penwidth=2];
+  2 [style=filled, fillcolor=lightblue];
+  0 -> 1 [label=\"a\"];
+  1 -> 2 [label=\"ε\"];
+  2 -> 3 [label=\"b\"];
+}
+");
+  }
+
+  #[test]
+  fn dfa_trace_stops_when_dead() {
+    let dfa = DFAOne::from_regexp("(a|b)*abb", "ab");
+    let res = trace(&dfa, "abba");
+    assert_eq!(res.steps.len(), 5);
+    assert!(res.steps[3].is_accept);
+    assert!(res.steps.iter().all(|step| step.closure_added().is_empty()));
+
+    let res = trace(&DFAOne::from_regexp("ab", "ab"), "bab");
+    assert_eq!(res.steps.len(), 2);
+    assert!(res.steps[1].is_dead);
+    assert_eq!(res.steps[1].states, Vec::<usize>::new());
+    assert_eq!(res.rest, "ab");
+    assert!(res.to_table().ends_with("| yes\nunread: \"ab\"\n"));
+  }
+}
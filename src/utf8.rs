@@ -0,0 +1,156 @@
+use super::automaton::Automaton;
+use super::convert_nfa_to_dfa::convert_nfa_to_dfa;
+use super::dfa::DFAOne;
+use super::nfa::NFAOne;
+
+// Byte automata are plain char automata where byte b is read as the char U+00b,
+// so every simulation and conversion works on them unchanged.
+pub fn bytes_as_chars(bytes: &[u8]) -> String {
+  bytes.iter().map(|&b| b as char).collect()
+}
+
+// the 256 chars standing for the bytes, the input alphabet of byte DFAs
+pub fn byte_alphabet() -> String {
+  (0..=255u8).map(|b| b as char).collect()
+}
+
+pub fn test_bytes<A: Automaton>(automaton: &A, bytes: &[u8]) -> bool {
+  automaton.test(&bytes_as_chars(bytes))
+}
+
+const MAX_ENCODED: [u32; 3] = [0x7F, 0x7FF, 0xFFFF]; // last scalar value of 1, 2 and 3 bytes
+
+// byte range sequences whose concatenations encode exactly the chars lo..=hi, in order.
+// Each sequence is one UTF-8 length and its ranges are independent of each other.
+pub fn utf8_sequences(lo: char, hi: char) -> Vec<Vec<(u8, u8)>> {
+  let mut res = vec![];
+  let mut stack = vec![(lo as u32, hi as u32)];
+  'split: while let Some((start, end)) = stack.pop() {
+    if start > end {
+      continue;
+    }
+    // the ends are chars, but the range may still span the surrogates
+    if start < 0xD800 && end > 0xDFFF {
+      stack.push((0xE000, end));
+      stack.push((start, 0xD7FF));
+      continue;
+    }
+    for max in MAX_ENCODED {
+      if start <= max && end > max {
+        stack.push((max + 1, end));
+        stack.push((start, max));
+        continue 'split;
+      }
+    }
+    if end <= 0x7F {
+      res.push(vec![(start as u8, end as u8)]);
+      continue;
+    }
+    // split until every continuation byte spans its full 80..=BF range below the
+    // first byte where start and end differ
+    for i in 1..4 {
+      let m = (1u32 << (6 * i)) - 1;
+      if start & !m != end & !m {
+        if start & m != 0 {
+          stack.push(((start | m) + 1, end));
+          stack.push((start, start | m));
+          continue 'split;
+        }
+        if end & m != m {
+          stack.push((end & !m, end));
+          stack.push((start, (end & !m) - 1));
+          continue 'split;
+        }
+      }
+    }
+    let mut start_buf = [0; 4];
+    let mut end_buf = [0; 4];
+    let start_bytes = char::from_u32(start).unwrap().encode_utf8(&mut start_buf).as_bytes();
+    let end_bytes = char::from_u32(end).unwrap().encode_utf8(&mut end_buf).as_bytes();
+    res.push(start_bytes.iter().zip(end_bytes).map(|(&l, &h)| (l, h)).collect());
+  }
+  res
+}
+
+impl DFAOne {
+  pub fn bytes_from_regexp(reg_exp: &str, any_byte: bool) -> DFAOne {
+    let alphabet = byte_alphabet();
+    convert_nfa_to_dfa(NFAOne::bytes_from_regexp(reg_exp, any_byte), &alphabet).state_minimization(&alphabet)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn utf8_sequences_full_range() {
+    assert_eq!(utf8_sequences('\0', char::MAX), vec![
+      vec![(0x00, 0x7F)],
+      vec![(0xC2, 0xDF), (0x80, 0xBF)],
+      vec![(0xE0, 0xE0), (0xA0, 0xBF), (0x80, 0xBF)],
+      vec![(0xE1, 0xEC), (0x80, 0xBF), (0x80, 0xBF)],
+      vec![(0xED, 0xED), (0x80, 0x9F), (0x80, 0xBF)],
+      vec![(0xEE, 0xEF), (0x80, 0xBF), (0x80, 0xBF)],
+      vec![(0xF0, 0xF0), (0x90, 0xBF), (0x80, 0xBF), (0x80, 0xBF)],
+      vec![(0xF1, 0xF3), (0x80, 0xBF), (0x80, 0xBF), (0x80, 0xBF)],
+      vec![(0xF4, 0xF4), (0x80, 0x8F), (0x80, 0xBF), (0x80, 0xBF)],
+    ]);
+  }
+
+  #[test]
+  fn utf8_sequences_exact() {
+    let matches = |sequences: &[Vec<(u8, u8)>], bytes: &[u8]| {
+      sequences.iter().any(|seq| {
+        seq.len() == bytes.len() && seq.iter().zip(bytes).all(|(&(lo, hi), &b)| lo <= b && b <= hi)
+      })
+    };
+    for (lo, hi) in [('a', 'z'), ('\u{7A}', '\u{801}'), ('\u{3B1}', '\u{1F600}'), ('\u{D7FE}', '\u{E001}')] {
+      let sequences = utf8_sequences(lo, hi);
+      for code in (lo as u32).saturating_sub(70)..=(hi as u32 + 70) {
+        if let Some(chr) = char::from_u32(code) {
+          let mut buf = [0; 4];
+          let expected = lo <= chr && chr <= hi;
+          assert_eq!(matches(&sequences, chr.encode_utf8(&mut buf).as_bytes()), expected, "{:?}", chr);
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn byte_automata() {
+    let reg_exp = "(a|λ)*\\p{Greek}é";
+    let nfa = NFAOne::bytes_from_regexp(reg_exp, false);
+    let dfa = DFAOne::bytes_from_regexp(reg_exp, false);
+    for (s, expected) in [("aλaπé", true), ("ωé", true), ("aé", false), ("λλ", false)] {
+      assert_eq!(test_bytes(&nfa, s.as_bytes()), expected, "{}", s);
+      assert_eq!(test_bytes(&dfa, s.as_bytes()), expected, "{}", s);
+    }
+    // the second byte of é alone, not valid UTF-8
+    assert!(!test_bytes(&dfa, b"\xCF\x89\xA9"));
+  }
+
+  #[test]
+  fn any_byte() {
+    let strict = DFAOne::bytes_from_regexp("a\\p{Any}*b", false);
+    let lenient = DFAOne::bytes_from_regexp("a\\p{Any}*b", true);
+    assert!(test_bytes(&strict, "aπ€b".as_bytes()));
+    assert!(test_bytes(&lenient, "aπ€b".as_bytes()));
+    assert!(!test_bytes(&strict, b"a\xFF\xC3b"));
+    assert!(test_bytes(&lenient, b"a\xFF\xC3b"));
+  }
+
+  #[test]
+  fn byte_automata_share_the_syntax() {
+    // case folding and counted repetition come with the shared construction
+    let nfa = NFAOne::bytes_from_regexp("(?i)é{2}λ?", false);
+    assert!(test_bytes(&nfa, "éÉΛ".as_bytes()));
+    assert!(!test_bytes(&nfa, "é".as_bytes()));
+  }
+
+  #[test]
+  #[should_panic(expected = "^ and $ are only supported by DFAOne, NFAOne and BacktrackOne")]
+  fn byte_automata_reject_anchors() {
+    NFAOne::bytes_from_regexp("^a", false);
+  }
+}
@@ -0,0 +1,286 @@
+use super::dfa::DFAOne;
+use std::collections::VecDeque;
+
+// the transitions of a DFA over a fixed alphabet, with the states that lie on
+// some path from the start to an accept state
+struct Table {
+  edges: Vec<Vec<(char, usize)>>, // sorted by char, so walks visit strings in lexicographic order
+  accept: Vec<bool>,
+  live: Vec<bool>,
+}
+
+impl Table {
+  fn new(dfa: &DFAOne, input: &str) -> Self {
+    let mut alphabet: Vec<char> = input.chars().collect();
+    alphabet.sort_unstable();
+    alphabet.dedup();
+    let edges: Vec<Vec<(char, usize)>> = (0..dfa.states_size)
+      .map(|s| alphabet.iter().filter_map(|&chr| (dfa.transition_func)(s, chr).map(|to| (chr, to))).collect())
+      .collect();
+    let mut accept = vec![false; dfa.states_size];
+    for &s in &dfa.accept {
+      accept[s] = true;
+    }
+
+    let mut reachable = vec![false; dfa.states_size];
+    let mut stack: Vec<usize> = dfa.start.into_iter().collect();
+    while let Some(s) = stack.pop() {
+      if !reachable[s] {
+        reachable[s] = true;
+        stack.extend(edges[s].iter().map(|&(_, to)| to));
+      }
+    }
+    let mut reverse_edges = vec![vec![]; dfa.states_size];
+    for (from, out) in edges.iter().enumerate() {
+      for &(_, to) in out {
+        reverse_edges[to].push(from);
+      }
+    }
+    let mut live = vec![false; dfa.states_size];
+    let mut stack: Vec<usize> = dfa.accept.iter().copied().filter(|&s| reachable[s]).collect();
+    while let Some(s) = stack.pop() {
+      if reachable[s] && !live[s] {
+        live[s] = true;
+        stack.extend(reverse_edges[s].iter().copied());
+      }
+    }
+    Table { edges, accept, live }
+  }
+
+  fn live_edges(&self, s: usize) -> impl Iterator<Item = (char, usize)> + '_ {
+    self.edges[s].iter().copied().filter(move |&(_, to)| self.live[to])
+  }
+
+  // the number of empty strings leading from each state to an accept state
+  fn accept_ways(&self) -> Vec<Option<u128>> {
+    self.accept.iter().map(|&accept| Some(accept as u128)).collect()
+  }
+
+  // the counts for strings one char longer than those of `prev`, None once a count
+  // doesn't fit in u128
+  fn next_ways(&self, prev: &[Option<u128>]) -> Vec<Option<u128>> {
+    (0..self.edges.len())
+      .map(|s| self.live_edges(s).try_fold(0u128, |acc, (_, to)| acc.checked_add(prev[to]?)))
+      .collect()
+  }
+
+  // ways[l][s] is the number of strings of length l leading from s to an accept state
+  fn ways(&self, len: usize) -> Vec<Vec<Option<u128>>> {
+    let mut ways = vec![self.accept_ways()];
+    for _ in 0..len {
+      let next = self.next_ways(ways.last().unwrap());
+      ways.push(next);
+    }
+    ways
+  }
+}
+
+// a uniform random number below `bound`, rejecting the draws past the last full multiple
+fn random_below(bound: u128, rng: &mut impl FnMut() -> u64) -> u128 {
+  let limit = u128::MAX - u128::MAX % bound;
+  loop {
+    let r = ((rng() as u128) << 64) | rng() as u128;
+    if r < limit {
+      return r % bound;
+    }
+  }
+}
+
+// Every method here only looks at the chars of `input`, like state_minimization.
+impl DFAOne {
+  // the shortest accepted string, the lexicographically smallest among equally short ones
+  pub fn shortest_accepted(&self, input: &str) -> Option<String> {
+    let table = Table::new(self, input);
+    let start = self.start.filter(|&s| table.live[s])?;
+    let mut parents: Vec<Option<(usize, char)>> = vec![None; self.states_size];
+    let mut visited = vec![false; self.states_size];
+    let mut queue = VecDeque::from(vec![start]);
+    visited[start] = true;
+    while let Some(s) = queue.pop_front() {
+      if table.accept[s] {
+        let mut res = vec![];
+        let mut curr = s;
+        while let Some((parent, chr)) = parents[curr] {
+          res.push(chr);
+          curr = parent;
+        }
+        return Some(res.into_iter().rev().collect());
+      }
+      for (chr, to) in table.live_edges(s) {
+        if !visited[to] {
+          visited[to] = true;
+          parents[to] = Some((s, chr));
+          queue.push_back(to);
+        }
+      }
+    }
+    unreachable!("a live start state reaches an accept state")
+  }
+
+  pub fn is_empty(&self, input: &str) -> bool {
+    let table = Table::new(self, input);
+    !self.start.is_some_and(|s| table.live[s])
+  }
+
+  // finite exactly when no cycle runs through the live states, found by peeling
+  // off states without incoming live edges until none are left
+  pub fn is_finite(&self, input: &str) -> bool {
+    let table = Table::new(self, input);
+    let mut in_degree = vec![0; self.states_size];
+    for s in (0..self.states_size).filter(|&s| table.live[s]) {
+      for (_, to) in table.live_edges(s) {
+        in_degree[to] += 1;
+      }
+    }
+    let mut stack: Vec<usize> = (0..self.states_size).filter(|&s| table.live[s] && in_degree[s] == 0).collect();
+    let mut peeled = 0;
+    while let Some(s) = stack.pop() {
+      peeled += 1;
+      for (_, to) in table.live_edges(s) {
+        in_degree[to] -= 1;
+        if in_degree[to] == 0 {
+          stack.push(to);
+        }
+      }
+    }
+    peeled == table.live.iter().filter(|&&live| live).count()
+  }
+
+  // every accepted string up to `max_len` chars, shorter ones first and
+  // lexicographically ordered within the same length
+  pub fn enumerate(&self, input: &str, max_len: usize) -> Vec<String> {
+    let table = Table::new(self, input);
+    let mut res = vec![];
+    let mut level: Vec<(String, usize)> = self.start.filter(|&s| table.live[s]).map(|s| (String::new(), s)).into_iter().collect();
+    for len in 0..=max_len {
+      res.extend(level.iter().filter(|(_, s)| table.accept[*s]).map(|(prefix, _)| prefix.clone()));
+      if len == max_len {
+        break;
+      }
+      level = level
+        .iter()
+        .flat_map(|(prefix, s)| {
+          table.live_edges(*s).map(move |(chr, to)| (format!("{}{}", prefix, chr), to))
+        })
+        .collect();
+    }
+    res
+  }
+
+  // the number of accepted strings of exactly `len` chars, None when it doesn't fit in u128
+  pub fn count_strings(&self, input: &str, len: usize) -> Option<u128> {
+    let table = Table::new(self, input);
+    // only the counts for the current length are kept
+    let mut ways = table.accept_ways();
+    for _ in 0..len {
+      ways = table.next_ways(&ways);
+    }
+    match self.start {
+      Some(start) => ways[start],
+      None => Some(0),
+    }
+  }
+
+  // an accepted string of `len` chars drawn uniformly from all of them, None when
+  // there is none or their number doesn't fit in u128. `rng` returns uniformly
+  // distributed u64s.
+  pub fn sample(&self, input: &str, len: usize, rng: &mut impl FnMut() -> u64) -> Option<String> {
+    let table = Table::new(self, input);
+    let ways = table.ways(len);
+    let mut s = self.start?;
+    if ways[len][s]? == 0 {
+      return None;
+    }
+    // no count on the way is above the total, so none of them overflowed
+    let count = |l: usize, s: usize| ways[l][s].unwrap();
+    let mut res = String::new();
+    for remaining in (1..=len).rev() {
+      // pick the next char in proportion to the number of ways to finish after it
+      let mut pick = random_below(count(remaining, s), rng);
+      for (chr, to) in table.live_edges(s) {
+        if pick < count(remaining - 1, to) {
+          res.push(chr);
+          s = to;
+          break;
+        }
+        pick -= count(remaining - 1, to);
+      }
+    }
+    Some(res)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::automaton::Automaton;
+  use std::collections::HashMap;
+
+  #[test]
+  fn witnesses() {
+    let dfa = DFAOne::from_regexp("(a|b)*abb", "ab");
+    assert_eq!(dfa.shortest_accepted("ab"), Some("abb".to_string()));
+    assert_eq!(DFAOne::from_regexp("b+|a{3}", "ab").shortest_accepted("ab"), Some("b".to_string()));
+    assert_eq!(DFAOne::from_regexp("a*", "a").shortest_accepted("a"), Some("".to_string()));
+    assert_eq!(DFAOne::from_regexp("a&b", "ab").shortest_accepted("ab"), None);
+    assert!(DFAOne::from_regexp("a&b", "ab").is_empty("ab"));
+    assert!(!dfa.is_empty("ab"));
+    // only reachable over a char missing from the input
+    assert!(DFAOne::from_regexp("ab", "ab").is_empty("b"));
+
+    assert!(!dfa.is_finite("ab"));
+    assert!(DFAOne::from_regexp("(a|b){2,3}c?", "abc").is_finite("abc"));
+    assert!(DFAOne::from_regexp("a(b*&())", "ab").is_finite("ab"));
+    assert!(!DFAOne::from_regexp("a(b*&~())", "ab").is_finite("ab"));
+  }
+
+  #[test]
+  fn enumerate_and_count() {
+    let dfa = DFAOne::from_regexp("(ba|a)*", "ab");
+    assert_eq!(dfa.enumerate("ba", 3), vec!["", "a", "aa", "ba", "aaa", "aba", "baa"]);
+    let fibonacci = [1, 1, 2, 3, 5, 8, 13, 21, 34, 55];
+    for (len, &count) in fibonacci.iter().enumerate() {
+      assert_eq!(dfa.count_strings("ab", len), Some(count));
+      assert_eq!(dfa.enumerate("ab", len).iter().filter(|s| s.len() == len).count() as u128, count);
+    }
+    assert_eq!(DFAOne::from_regexp("(a|b|c)*", "abc").count_strings("abc", 80), Some(3u128.pow(80)));
+    assert_eq!(DFAOne::from_regexp("a&b", "ab").count_strings("ab", 1), Some(0));
+  }
+
+  #[test]
+  fn count_overflow() {
+    let dfa = DFAOne::from_regexp("(a|b)*", "ab");
+    assert_eq!(dfa.count_strings("ab", 127), Some(1 << 127));
+    assert_eq!(dfa.count_strings("ab", 128), None);
+    assert_eq!(dfa.sample("ab", 128, &mut || 0), None);
+    assert_eq!(dfa.sample("ab", 127, &mut || 0), Some("a".repeat(127)));
+    // the state after "ab" overflows at this length, only the start's count matters
+    let dfa = DFAOne::from_regexp("ab(a|b)*", "ab");
+    assert_eq!(dfa.count_strings("ab", 128), Some(1 << 126));
+    assert!(dfa.sample("ab", 128, &mut || 7).unwrap().starts_with("ab"));
+  }
+
+  #[test]
+  fn uniform_samples() {
+    // splitmix64, the low bits of a plain linear congruential generator repeat too soon
+    let mut seed = 7u64;
+    let mut rng = || {
+      seed = seed.wrapping_add(0x9E3779B97F4A7C15);
+      let z = (seed ^ (seed >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+      let z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+      z ^ (z >> 31)
+    };
+    let dfa = DFAOne::from_regexp("(ba|a)*", "ab");
+    // the 8 strings of length 5 should each come up about 1/8 of the time
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for _ in 0..8000 {
+      let s = dfa.sample("ab", 5, &mut rng).unwrap();
+      assert!(dfa.test(&s), "{}", s);
+      *seen.entry(s).or_default() += 1;
+    }
+    assert_eq!(seen.len(), 8);
+    assert!(seen.values().all(|&n| (850..1150).contains(&n)), "{:?}", seen);
+    assert_eq!(dfa.sample("ab", 0, &mut rng), Some("".to_string()));
+    assert_eq!(DFAOne::from_regexp("aa*", "a").sample("a", 0, &mut rng), None);
+  }
+}